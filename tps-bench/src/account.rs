@@ -1,10 +1,13 @@
 #![allow(clippy::mutable_key_type)]
 use crate::config::TransactionType;
-use crate::global::{CELLBASE_MATURITY, MIN_SECP_CELL_CAPACITY, SIGHASH_ALL_TYPE_HASH};
+use crate::global::{
+    CELLBASE_MATURITY, MIN_SECP_CELL_CAPACITY, RPC_RETRY_POLICY, SIGHASH_ALL_TYPE_HASH,
+};
 use crate::net::Net;
-use crate::rpc::Jsonrpc;
+use crate::rpc::{Jsonrpc, RpcError};
+use crate::send_error::{SendError, SendErrorCounter, SendErrorKind};
 use crate::transfer::{construct_unsigned_transaction, sign_transaction};
-use crate::util::estimate_fee;
+use crate::util::{call_until_ok, estimate_fee};
 use crate::utxo::UTXO;
 
 use ckb_crypto::secp::Privkey;
@@ -15,7 +18,8 @@ use ckb_types::packed::{Byte32, CellOutput, OutPoint, Script};
 use ckb_types::prelude::*;
 use ckb_types::H160;
 use crossbeam_channel::{bounded, Receiver, Sender};
-use log::info;
+use log::{error, info, warn};
+use serde_json::json;
 use std::collections::HashMap;
 use std::str::FromStr;
 use std::thread::{sleep, spawn};
@@ -57,10 +61,13 @@ impl Account {
         &self,
         rpc: &Jsonrpc,
         until_header: &HeaderView,
-    ) -> (
-        HashMap<OutPoint, CellOutput>,
-        HashMap<OutPoint, (BlockNumber, CellOutput)>,
-    ) {
+    ) -> Result<
+        (
+            HashMap<OutPoint, CellOutput>,
+            HashMap<OutPoint, (BlockNumber, CellOutput)>,
+        ),
+        RpcError,
+    > {
         info!(
             "[START] Account::pull_until({}, {})",
             rpc.uri(),
@@ -81,10 +88,10 @@ impl Account {
                 );
             }
 
-            let block: BlockView = rpc
-                .get_block_by_number(number)
-                .expect("get_block_by_number")
-                .into();
+            let block: BlockView =
+                call_until_ok("get_block_by_number", || rpc.get_block_by_number(number))?
+                    .ok_or_else(|| RpcError::NotFound(format!("get_block_by_number({})", number)))?
+                    .into();
 
             let (matured, unmatured) = self.get_owned_utxos(&block);
             // Add newly UTXOs
@@ -119,7 +126,7 @@ impl Account {
             unmatureds.len()
         );
 
-        (utxoset, unmatureds)
+        Ok((utxoset, unmatureds))
     }
 
     pub fn pull_from_block_number(
@@ -128,8 +135,12 @@ impl Account {
         block_number: BlockNumber,
         utxoset: &mut HashMap<OutPoint, CellOutput>,
         unmatureds: &mut HashMap<OutPoint, (BlockNumber, CellOutput)>,
-    ) {
-        let block: BlockView = net.get_block_by_number(block_number).unwrap().into();
+    ) -> Result<(), RpcError> {
+        let block: BlockView = call_until_ok("get_block_by_number", || {
+            net.get_block_by_number(block_number)
+        })?
+        .ok_or_else(|| RpcError::NotFound(format!("get_block_by_number({})", block_number)))?
+        .into();
         let (matured, unmatured) = self.get_owned_utxos(&block);
         for utxo in matured {
             utxoset.insert(utxo.out_point().clone(), utxo.output().clone());
@@ -161,6 +172,7 @@ impl Account {
             utxoset.insert(utxo.out_point().clone(), utxo.output().clone());
             unmatureds.remove(utxo.out_point());
         }
+        Ok(())
     }
 
     pub fn construct_utxo_vec(
@@ -192,15 +204,22 @@ impl Account {
     ) {
         let mut current_header = from_header;
         loop {
-            if let Some(header) = net.get_fixed_header(current_header.number() + 1) {
+            let fixed_header = net
+                .get_fixed_header(current_header.number() + 1)
+                .unwrap_or_else(|err| {
+                    error!("account.pull_forever get_fixed_header error: {}", err);
+                    None
+                });
+            if let Some(header) = fixed_header {
                 // Chain has been re-organized! Rollback to the fixed point!
                 if header.parent_hash() != current_header.hash() {
                     current_header = rollback_for_reorg(&net, &current_header);
                     continue;
                 }
 
-                // net.get_block return None when re-organize
-                if let Some(block) = net.get_block(header.hash()) {
+                // net.get_block return None when re-organize, and the block is fetched again in
+                // the next round on errors
+                if let Ok(Some(block)) = net.get_block(header.hash()) {
                     current_header = header;
                     let block: BlockView = block.into();
                     let (matured, unmatured) = self.get_owned_utxos(&block);
@@ -243,16 +262,27 @@ impl Account {
             outputs_count * MIN_SECP_CELL_CAPACITY + estimate_fee(outputs_count);
         let (mut inputs, mut input_total_capacity) = (Vec::new(), 0);
 
+        // A failed transaction only loses its inputs, so the errors are logged and counted rather
+        // than aborting the whole run
+        let send_error_counter = SendErrorCounter::new(net.endpoints().len());
         let senders = net
             .endpoints()
             .iter()
-            .map(|rpc| {
+            .enumerate()
+            .map(|(endpoint, rpc)| {
                 let rpc = rpc.clone();
+                let send_error_counter = send_error_counter.clone();
                 let (sender, receiver) = bounded(1000);
                 spawn(move || {
                     while let Ok(transaction) = receiver.recv() {
-                        if let Err(err) = retry_send(&rpc, &transaction) {
-                            panic!(err)
+                        match retry_send(&rpc, &transaction, endpoint, &send_error_counter) {
+                            Ok(()) => send_error_counter.record_accepted(endpoint),
+                            Err(err) => error!(
+                                "account.transfer_forever send_transaction({}, {:#x}) error: {}",
+                                rpc.uri(),
+                                transaction.hash(),
+                                err
+                            ),
                         }
                     }
                 });
@@ -282,7 +312,10 @@ impl Account {
                 break;
             }
         }
-        info!("START account.transfer_forever");
+        info!(
+            "END account.transfer_forever, send errors: {}",
+            json!(send_error_counter.metrics(&net))
+        );
     }
 
     pub fn privkey(&self) -> &Privkey {
@@ -316,25 +349,43 @@ impl Account {
     }
 }
 
-fn is_matured(tip_number: BlockNumber, number: BlockNumber) -> bool {
+pub fn is_matured(tip_number: BlockNumber, number: BlockNumber) -> bool {
     tip_number > number + 1800 * *CELLBASE_MATURITY.lock().unwrap()
 }
 
-fn retry_send(rpc: &Jsonrpc, transaction: &core::TransactionView) -> Result<(), String> {
+// Send `transaction`, backing off by `RPC_RETRY_POLICY` while the pool is full, and after
+// transport errors up to `max_retries` times. Every error is counted into `send_error_counter`.
+fn retry_send(
+    rpc: &Jsonrpc,
+    transaction: &core::TransactionView,
+    endpoint: usize,
+    send_error_counter: &SendErrorCounter,
+) -> Result<(), SendError> {
+    let policy = *RPC_RETRY_POLICY.lock().unwrap();
+    let mut backoff = Duration::from_millis(policy.initial_backoff_ms);
+    let mut transport_retries = 0;
     loop {
-        match rpc.send_transaction_result(transaction.data().into()) {
-            Err(err) => {
-                let err_str = err.to_string();
-                if err_str.contains("TransactionPoolFull") || err_str.contains("PoolIsFull") {
-                    sleep(Duration::from_secs(1));
-                    continue;
-                }
-                return Err(err.to_string());
-            }
-            Ok(_) => {
-                return Ok(());
+        let err = match rpc.send_transaction_result(transaction.data().into()) {
+            Ok(_) => return Ok(()),
+            Err(err) => err,
+        };
+        send_error_counter.record_error(endpoint, err.kind);
+        match err.kind {
+            SendErrorKind::PoolFull => {}
+            SendErrorKind::Transport if transport_retries < policy.max_retries => {
+                transport_retries += 1;
             }
+            _ => return Err(err),
         }
+        warn!(
+            "account.transfer_forever send_transaction({}, {:#x}) error: {}, retry in {:?}",
+            rpc.uri(),
+            transaction.hash(),
+            err,
+            backoff
+        );
+        sleep(backoff);
+        backoff = (backoff * 2).min(Duration::from_millis(policy.max_backoff_ms));
     }
 }
 
@@ -342,7 +393,10 @@ fn rollback_for_reorg(net: &Net, old_header: &HeaderView) -> HeaderView {
     // NOTE: We cannot find the exactly fixed point of old_header and new tip header based on ckb
     // rpc interfaces.
 
-    net.get_header_by_number(old_header.number().saturating_sub(1000))
-        .unwrap_or_else(|| panic!("rollback_for_org(old_header={:?})", old_header))
-        .into()
+    call_until_ok("get_header_by_number", || {
+        net.get_header_by_number(old_header.number().saturating_sub(1000))
+    })
+    .unwrap_or_else(|err| panic!("get_header_by_number error: {}", err))
+    .unwrap_or_else(|| panic!("rollback_for_org(old_header={:?})", old_header))
+    .into()
 }
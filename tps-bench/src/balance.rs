@@ -0,0 +1,279 @@
+#![allow(clippy::mutable_key_type)]
+use crate::account::{is_matured, Account};
+use crate::command::OutputFormat;
+use crate::metric::print_rows;
+use crate::net::Net;
+use crate::rpc::RpcError;
+use crate::util::call_until_ok;
+
+use ckb_types::core::{BlockNumber, HeaderView};
+use ckb_types::packed::{Byte32, CellOutput};
+use ckb_types::prelude::*;
+use log::info;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub const SHANNONS_PER_CKB: u64 = 1_0000_0000;
+
+// The chain RPC `get_cells_by_lock_hash` accepts at most 100 blocks per query.
+const INDEXER_PAGE_BLOCKS: u64 = 100;
+
+// The bucket upper bounds of cell capacities, in CKBytes.
+const HISTOGRAM_BOUNDS: &[u64] = &[
+    62,
+    100,
+    1_000,
+    10_000,
+    100_000,
+    1_000_000,
+    10_000_000,
+    u64::max_value(),
+];
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Balance {
+    pub owner: String,
+    pub lock_hash: String,
+    pub tip_number: BlockNumber,
+    pub live_capacity: u64,
+    pub matured_capacity: u64,
+    pub immature_capacity: u64,
+    pub cells_count: u64,
+    pub matured_cells_count: u64,
+    pub immature_cells_count: u64,
+    pub histogram: Vec<HistogramBucket>,
+}
+
+/// The count of cells whose capacity is in `[lower, upper)` CKBytes.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct HistogramBucket {
+    pub lower: u64,
+    pub upper: Option<u64>,
+    pub cells_count: u64,
+    pub capacity: u64,
+}
+
+impl Balance {
+    /// Scan the chain `[0, until_header]` via `Account::pull_until` and report the live cells
+    /// owned by `account`.
+    pub fn of_account(
+        net: &Net,
+        owner: &str,
+        account: &Account,
+        until_header: &HeaderView,
+    ) -> Result<Self, RpcError> {
+        let (matureds, unmatureds) = account.pull_until(net, until_header)?;
+        let matureds = matureds.values().cloned().collect::<Vec<_>>();
+        let unmatureds = unmatureds
+            .values()
+            .map(|(_, output)| output.clone())
+            .collect::<Vec<_>>();
+        Ok(Self::new(
+            owner.to_owned(),
+            account.lock_hash(),
+            until_header.number(),
+            &matureds,
+            &unmatureds,
+        ))
+    }
+
+    /// Query the live cells locked by `lock_hash` via the chain RPC `get_cells_by_lock_hash`,
+    /// scanning `[0, until_header]` page by page. No indexing is required.
+    pub fn of_lock_hash(
+        net: &Net,
+        lock_hash: &Byte32,
+        until_header: &HeaderView,
+    ) -> Result<Self, RpcError> {
+        info!(
+            "[START] Balance::of_lock_hash({}, {})",
+            lock_hash,
+            until_header.number()
+        );
+        let tip_number = until_header.number();
+        let mut block_numbers: HashMap<Byte32, BlockNumber> = HashMap::default();
+        let (mut matureds, mut unmatureds) = (Vec::new(), Vec::new());
+        for from in (0..=tip_number).step_by(INDEXER_PAGE_BLOCKS as usize) {
+            let to = (from + INDEXER_PAGE_BLOCKS - 1).min(tip_number);
+            let cells = call_until_ok("get_cells_by_lock_hash", || {
+                net.get_cells_by_lock_hash(lock_hash.clone(), from, to)
+            })?;
+            for cell in cells {
+                let output = CellOutput::new_builder()
+                    .capacity(cell.capacity.value().pack())
+                    .build();
+                if !cell.cellbase {
+                    matureds.push(output);
+                    continue;
+                }
+
+                let block_hash = cell.block_hash.pack();
+                let block_number = match block_numbers.get(&block_hash) {
+                    Some(block_number) => *block_number,
+                    None => {
+                        let header: HeaderView =
+                            call_until_ok("get_header", || net.get_header(block_hash.clone()))?
+                                .ok_or_else(|| {
+                                    RpcError::NotFound(format!("get_header({})", block_hash))
+                                })?
+                                .into();
+                        block_numbers.insert(block_hash, header.number());
+                        header.number()
+                    }
+                };
+                if is_matured(tip_number, block_number) {
+                    matureds.push(output);
+                } else {
+                    unmatureds.push(output);
+                }
+            }
+        }
+        info!("[END] Balance::of_lock_hash({}, {})", lock_hash, tip_number);
+        Ok(Self::new(
+            format!("{:#x}", lock_hash),
+            lock_hash.clone(),
+            tip_number,
+            &matureds,
+            &unmatureds,
+        ))
+    }
+
+    fn new(
+        owner: String,
+        lock_hash: Byte32,
+        tip_number: BlockNumber,
+        matureds: &[CellOutput],
+        unmatureds: &[CellOutput],
+    ) -> Self {
+        let capacity_of = |outputs: &[CellOutput]| -> u64 {
+            outputs
+                .iter()
+                .map(|output| -> u64 { output.capacity().unpack() })
+                .sum()
+        };
+        let matured_capacity = capacity_of(matureds);
+        let immature_capacity = capacity_of(unmatureds);
+        let mut histogram = HISTOGRAM_BOUNDS
+            .iter()
+            .scan(0, |lower, upper| {
+                let bucket = HistogramBucket {
+                    lower: *lower,
+                    upper: Some(*upper).filter(|upper| *upper != u64::max_value()),
+                    cells_count: 0,
+                    capacity: 0,
+                };
+                *lower = *upper;
+                Some(bucket)
+            })
+            .collect::<Vec<_>>();
+        for output in matureds.iter().chain(unmatureds.iter()) {
+            let capacity: u64 = output.capacity().unpack();
+            let index = HISTOGRAM_BOUNDS
+                .iter()
+                .position(|upper| capacity / SHANNONS_PER_CKB < *upper)
+                .expect("the last bound is u64::max_value()");
+            histogram[index].cells_count += 1;
+            histogram[index].capacity += capacity;
+        }
+
+        Self {
+            owner,
+            lock_hash: format!("{:#x}", lock_hash),
+            tip_number,
+            live_capacity: matured_capacity + immature_capacity,
+            matured_capacity,
+            immature_capacity,
+            cells_count: (matureds.len() + unmatureds.len()) as u64,
+            matured_cells_count: matureds.len() as u64,
+            immature_cells_count: unmatureds.len() as u64,
+            histogram,
+        }
+    }
+
+    pub fn print_table(&self) {
+        if self.owner == self.lock_hash {
+            println!("{}", self.lock_hash);
+        } else {
+            println!("{} {}", self.owner, self.lock_hash);
+        }
+        println!("  tip_number:        {}", self.tip_number);
+        println!(
+            "  live capacity:     {} CKB in {} cells",
+            self.live_capacity / SHANNONS_PER_CKB,
+            self.cells_count
+        );
+        println!(
+            "  matured capacity:  {} CKB in {} cells",
+            self.matured_capacity / SHANNONS_PER_CKB,
+            self.matured_cells_count
+        );
+        println!(
+            "  immature capacity: {} CKB in {} cells",
+            self.immature_capacity / SHANNONS_PER_CKB,
+            self.immature_cells_count
+        );
+        println!(
+            "  {:>24} | {:>10} | {:>16}",
+            "cell size (CKB)", "cells", "capacity (CKB)"
+        );
+        for bucket in self.histogram.iter() {
+            let range = match bucket.upper {
+                Some(upper) => format!("[{}, {})", bucket.lower, upper),
+                None => format!("[{}, +inf)", bucket.lower),
+            };
+            println!(
+                "  {:>24} | {:>10} | {:>16}",
+                range,
+                bucket.cells_count,
+                bucket.capacity / SHANNONS_PER_CKB
+            );
+        }
+    }
+}
+
+/// Print `balances` as rows via `metric::print_rows`, one column per histogram bucket.
+pub fn print_balance_rows(format: OutputFormat, balances: &[Balance]) {
+    let mut headers = [
+        "owner",
+        "lock_hash",
+        "tip_number",
+        "live_capacity",
+        "matured_capacity",
+        "immature_capacity",
+        "cells_count",
+        "matured_cells_count",
+        "immature_cells_count",
+    ]
+    .iter()
+    .map(|header| (*header).to_owned())
+    .collect::<Vec<_>>();
+    if let Some(balance) = balances.first() {
+        headers.extend(balance.histogram.iter().map(|bucket| match bucket.upper {
+            Some(upper) => format!("cells [{}, {}) CKB", bucket.lower, upper),
+            None => format!("cells [{}, +inf) CKB", bucket.lower),
+        }));
+    }
+    let rows = balances
+        .iter()
+        .map(|balance| {
+            let mut row = vec![
+                balance.owner.clone(),
+                balance.lock_hash.clone(),
+                balance.tip_number.to_string(),
+                balance.live_capacity.to_string(),
+                balance.matured_capacity.to_string(),
+                balance.immature_capacity.to_string(),
+                balance.cells_count.to_string(),
+                balance.matured_cells_count.to_string(),
+                balance.immature_cells_count.to_string(),
+            ];
+            row.extend(
+                balance
+                    .histogram
+                    .iter()
+                    .map(|bucket| bucket.cells_count.to_string()),
+            );
+            row
+        })
+        .collect::<Vec<_>>();
+    print_rows(format, "balances", &headers, &rows);
+}
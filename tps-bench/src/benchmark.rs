@@ -1,17 +1,32 @@
 use crate::account::Account;
+use crate::client_monitor::{ClientMonitor, EMITTER_CHANNEL_CAPACITY};
+use crate::coin_selection::{CoinSelection, CoinSelector};
 use crate::config::TransactionType;
-use crate::global::{METHOD_TO_EVAL_NET_STABLE, METRICS_RECORDER, MIN_SECP_CELL_CAPACITY};
+use crate::global::{
+    BLOCK_ASSEMBLY_SAMPLES, METHOD_TO_EVAL_NET_STABLE, METRICS_RECORDER, MIN_SECP_CELL_CAPACITY,
+    RUN_ID,
+};
+use crate::latency::{spawn_commit_watcher, spawn_lifecycle_sampler, LatencyTracker};
+use crate::miner::BlockAssemblyMetrics;
 use crate::net::Net;
-use crate::net_monitor::{wait_network_stabled, MethodToEvalNetStable};
+use crate::net_monitor::{eval_sub_window_tps, wait_network_stabled, MethodToEvalNetStable};
+use crate::propagation::{
+    spawn_block_propagation_monitor, spawn_transaction_propagation_sampler,
+    TransactionPropagationTracker,
+};
 use crate::rpc::Jsonrpc;
+use crate::send_error::{SendErrorCounter, SendErrorKind};
+use crate::statistics::Summary;
 use crate::transfer::{construct_unsigned_transaction, sign_transaction};
-use crate::util::estimate_fee;
+use crate::txpool_series::set_bench_phase;
+use crate::util::{call_until_ok, estimate_fee};
 use crate::utxo::UTXO;
 use ckb_types::core::TransactionView;
 use crossbeam_channel::{bounded, Receiver, Sender};
 use log::info;
+use metrics::{counter, gauge, timing};
 use serde_derive::{Deserialize, Serialize};
-use serde_json::json;
+use serde_json::{json, Value};
 use std::io::Write;
 use std::thread::{sleep, spawn};
 use std::time::{Duration, Instant};
@@ -21,9 +36,79 @@ pub struct BenchmarkConfig {
     pub transaction_type: TransactionType,
     pub send_delay: u64, // micros
     pub method_to_eval_net_stable: Option<MethodToEvalNetStable>,
+    #[serde(default)]
+    pub coin_selection: CoinSelection,
+    /// The rate of the sent transactions to track stage by stage, in `[0, 1]`
+    #[serde(default = "default_lifecycle_sample_rate")]
+    pub lifecycle_sample_rate: f64,
+    /// The rate of the sent transactions to track across the other endpoints, in `[0, 1]`
+    #[serde(default = "default_propagation_sample_rate")]
+    pub propagation_sample_rate: f64,
+    /// Run the benchmark this many times and report the statistics of the results
+    #[serde(default = "default_repetitions")]
+    pub repetitions: u64,
+    /// Split the evaluation window of every run into this many sub-windows to sample the TPS
+    #[serde(default = "default_sub_windows")]
+    pub sub_windows: u64,
+    /// The result is marked unstable when its coefficient of variation exceeds this
+    #[serde(default = "default_max_coefficient_of_variation")]
+    pub max_coefficient_of_variation: f64,
+}
+
+pub fn default_lifecycle_sample_rate() -> f64 {
+    0.01
+}
+
+pub fn default_propagation_sample_rate() -> f64 {
+    0.01
+}
+
+pub fn default_repetitions() -> u64 {
+    1
+}
+
+pub fn default_sub_windows() -> u64 {
+    5
+}
+
+pub fn default_max_coefficient_of_variation() -> f64 {
+    0.1
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct BenchmarkStatistics {
+    /// The TPS of every repetition
+    repetitions: Summary,
+    /// The TPS of every sub-window of all the repetitions
+    sub_windows: Summary,
+    /// Judged by the repetitions if repeated, otherwise by the sub-windows
+    stable: bool,
+}
+
+impl BenchmarkStatistics {
+    pub fn new(
+        repetition_tps: &[u64],
+        sub_window_tps: &[u64],
+        max_coefficient_of_variation: f64,
+    ) -> Self {
+        let to_f64 = |tps: &[u64]| tps.iter().map(|tps| *tps as f64).collect::<Vec<_>>();
+        let repetitions = Summary::new(&to_f64(repetition_tps));
+        let sub_windows = Summary::new(&to_f64(sub_window_tps));
+        let judged_by = if repetitions.count >= 2 {
+            &repetitions
+        } else {
+            &sub_windows
+        };
+        Self {
+            stable: judged_by.coefficient_of_variation <= max_coefficient_of_variation,
+            repetitions,
+            sub_windows,
+        }
+    }
 }
 
 impl BenchmarkConfig {
+    /// Run the benchmark `repetitions` times and return the mean TPS.
     pub fn bench(
         &self,
         net: &Net,
@@ -32,15 +117,75 @@ impl BenchmarkConfig {
         sender_utxo_rx: &Receiver<UTXO>,
         send_delay: u64,
     ) -> u64 {
+        let (mut repetition_tps, mut sub_window_tps) = (Vec::new(), Vec::new());
+        for repetition in 0..self.repetitions.max(1) {
+            match self.bench_once(
+                net,
+                sender,
+                recipient,
+                sender_utxo_rx,
+                send_delay,
+                repetition,
+            ) {
+                Some((tps, sub_windows)) => {
+                    repetition_tps.push(tps);
+                    sub_window_tps.extend(sub_windows);
+                }
+                None => break,
+            }
+        }
+        if repetition_tps.is_empty() {
+            return 0;
+        }
+
+        let statistics = BenchmarkStatistics::new(
+            &repetition_tps,
+            &sub_window_tps,
+            self.max_coefficient_of_variation,
+        );
+        let result = json!({
+            "benchmark": {
+                "send_delay": send_delay,
+                "transaction_type": self.transaction_type,
+                "coin_selection": self.coin_selection,
+            },
+            "statistics": statistics,
+        });
+        record_result(&result);
+        info!("[BENCHMARK STATISTICS] {}", result);
+        repetition_tps.iter().sum::<u64>() / repetition_tps.len() as u64
+    }
+
+    // Return the TPS of the evaluation window and of its sub-windows, or `None` if the UTXOs
+    // run out before the network gets stable
+    fn bench_once(
+        &self,
+        net: &Net,
+        sender: &Account,
+        recipient: &Account,
+        sender_utxo_rx: &Receiver<UTXO>,
+        send_delay: u64,
+        repetition: u64,
+    ) -> Option<(u64, Vec<u64>)> {
+        set_bench_phase("wait_txpool_empty".to_owned());
         crate::net_monitor::wait_network_txpool_empty(&net);
+        *BLOCK_ASSEMBLY_SAMPLES.lock().unwrap() = Some(Vec::new());
+        set_bench_phase(format!(
+            "bench(send_delay={}, transaction_type={:?}, repetition={})",
+            send_delay, self.transaction_type, repetition
+        ));
 
-        let current_confirmed_tip = net.get_confirmed_tip_number();
+        let current_confirmed_tip = call_until_ok("net.get_confirmed_tip_number", || {
+            net.get_confirmed_tip_number()
+        })
+        .unwrap_or_else(|err| panic!("net.get_confirmed_tip_number error: {}", err));
         info!(
             "[BENCHMARK] {}",
             json!({
                 "benchmark": {
                     "send_delay": send_delay,
                     "transaction_type": self.transaction_type,
+                    "coin_selection": self.coin_selection,
                 },
                 "current_confirmed_tip_number": current_confirmed_tip
             })
@@ -59,33 +204,60 @@ impl BenchmarkConfig {
             net_notifier
         };
 
+        let latency_tracker = LatencyTracker::new(self.lifecycle_sample_rate);
+        let _commit_watcher = spawn_commit_watcher(net.clone(), latency_tracker.clone());
+        let _lifecycle_sampler = spawn_lifecycle_sampler(net.clone(), latency_tracker.clone());
+        let _block_propagation_monitor = spawn_block_propagation_monitor(net);
+        let propagation_tracker =
+            TransactionPropagationTracker::new(self.propagation_sample_rate, net.endpoints().len());
+        let _propagation_sampler =
+            spawn_transaction_propagation_sampler(net, propagation_tracker.clone());
+        let client_monitor = ClientMonitor::new(net.endpoints().len());
+        let send_error_counter = SendErrorCounter::new(net.endpoints().len());
         let txemitters = net
             .endpoints()
             .iter()
-            .map(|rpc| spawn_transaction_emitter(rpc.clone()))
+            .enumerate()
+            .map(|(endpoint, rpc)| {
+                spawn_transaction_emitter(
+                    endpoint,
+                    rpc.clone(),
+                    latency_tracker.clone(),
+                    propagation_tracker.clone(),
+                    client_monitor.clone(),
+                    send_error_counter.clone(),
+                )
+            })
             .collect::<Vec<_>>();
 
         let outputs_count = self.transaction_type.outputs_count() as u64;
         let min_input_total_capacity =
             outputs_count * MIN_SECP_CELL_CAPACITY + estimate_fee(outputs_count);
-        let (mut inputs, mut input_total_capacity) = (Vec::new(), 0);
+        let mut coin_selector = CoinSelector::new(self.coin_selection);
         let mut cursor = 0;
         let (mut sent, mut last_print_sent) = (0, Instant::now());
 
-        while let Ok(utxo) = sender_utxo_rx.recv() {
-            input_total_capacity += utxo.capacity();
-            inputs.push(utxo);
-            if input_total_capacity < min_input_total_capacity {
-                continue;
-            }
+        let mut utxo_wait_start = Instant::now();
+        while let Some(inputs) = coin_selector.select(sender_utxo_rx, min_input_total_capacity) {
+            client_monitor.record_utxo_wait(utxo_wait_start.elapsed());
 
             // Construct transaction
-            input_total_capacity = 0;
-            let raw_transaction =
-                construct_unsigned_transaction(&recipient, inputs.split_off(0), outputs_count);
+            let sign_start = Instant::now();
+            let raw_transaction = construct_unsigned_transaction(&recipient, inputs, outputs_count);
             let signed_transaction = sign_transaction(sender, raw_transaction);
+            client_monitor.record_sign(sign_start.elapsed());
+            timing!(
+                "tps_bench.bench.sign_transaction",
+                sign_start,
+                Instant::now()
+            );
+            gauge!(
+                "tps_bench.bench.utxo_channel_depth",
+                sender_utxo_rx.len() as i64
+            );
 
             // Send transaction
+            let dispatch_start = Instant::now();
             loop {
                 cursor = (cursor + 1) % txemitters.len();
                 if txemitters[cursor]
@@ -95,6 +267,13 @@ impl BenchmarkConfig {
                     break;
                 }
             }
+            let queue_depth = txemitters[cursor].len();
+            client_monitor.record_dispatch(dispatch_start.elapsed(), cursor, queue_depth);
+            gauge!(
+                "tps_bench.bench.emitter_queue_depth",
+                queue_depth as i64,
+                "endpoint" => net.endpoints()[cursor].uri().clone()
+            );
 
             sent += 1;
             if last_print_sent.elapsed() > Duration::from_secs(60) {
@@ -104,28 +283,47 @@ impl BenchmarkConfig {
 
             // Sleep every time sending transaction.
             sleep(Duration::from_micros(send_delay));
+            utxo_wait_start = Instant::now();
 
             if let Ok(metrics) = net_notifier.try_recv() {
+                let block_assembly = {
+                    let samples = BLOCK_ASSEMBLY_SAMPLES
+                        .lock()
+                        .unwrap()
+                        .take()
+                        .unwrap_or_default();
+                    BlockAssemblyMetrics::eval_per_endpoint(&samples)
+                };
+                let tps = metrics.tps();
+                let (from_number, end_number) = metrics.block_range();
+                let sub_window_tps = call_until_ok("eval_sub_window_tps", || {
+                    eval_sub_window_tps(net, from_number, end_number, self.sub_windows)
+                })
+                .unwrap_or_else(|err| panic!("eval_sub_window_tps error: {}", err));
                 let result = json!({
                     "benchmark": {
                     "send_delay": send_delay,
                     "transaction_type": self.transaction_type,
+                    "coin_selection": self.coin_selection,
                     },
+                    "repetition": repetition,
                     "metrics": metrics,
+                    "sub_window_tps": sub_window_tps,
+                    "block_assembly": block_assembly,
+                    "latency": latency_tracker.metrics(),
+                    "transaction_propagation": propagation_tracker.metrics(),
+                    "client": client_monitor.metrics(net),
+                    "send_errors": send_error_counter.metrics(net),
                 });
 
-                let recorder = METRICS_RECORDER.lock().unwrap();
-                if let Some(mut recorder) = recorder.as_ref() {
-                    let _ = recorder.write(result.to_string().as_bytes());
-                    let _ = recorder.write(b"\n");
-                    let _ = recorder.flush();
-                };
-
+                record_result(&result);
                 info!("[BENCHMARK RESULT] {}", result,);
-                return result["metrics"]["tps"].as_u64().expect("get tps");
+                set_bench_phase("idle".to_owned());
+                return Some((tps, sub_window_tps));
             }
         }
-        0
+        *BLOCK_ASSEMBLY_SAMPLES.lock().unwrap() = None;
+        None
     }
 
     pub fn find_best_bench(
@@ -135,20 +333,26 @@ impl BenchmarkConfig {
         recipient: &Account,
         sender_utxo_rx: &Receiver<UTXO>,
     ) -> u64 {
+        let bench = |send_delay: u64| {
+            let tps = self.bench(net, sender, recipient, sender_utxo_rx, send_delay);
+            info!(
+                "[FIND BEST TPS STEP] {}",
+                json!({ "send_delay": send_delay, "tps": tps })
+            );
+            tps
+        };
+
         let mut min_send_delay = self.send_delay;
-        let mut min_send_delay_tps =
-            self.bench(net, sender, recipient, sender_utxo_rx, self.send_delay);
+        let mut min_send_delay_tps = bench(self.send_delay);
 
         let mut max_send_delay = 1_000_000 / min_send_delay_tps;
-        let mut max_send_delay_tps =
-            self.bench(net, sender, recipient, sender_utxo_rx, max_send_delay);
+        let mut max_send_delay_tps = bench(max_send_delay);
 
         let mut nearly_send_delay_tps: Vec<u64> = Vec::new();
 
         while min_send_delay < max_send_delay - 1 {
             let mid_send_delay = (min_send_delay + max_send_delay) / 2;
-            let mid_send_delay_tps =
-                self.bench(net, sender, recipient, sender_utxo_rx, mid_send_delay);
+            let mid_send_delay_tps = bench(mid_send_delay);
             if max_send_delay - min_send_delay < 200 {
                 nearly_send_delay_tps.push(mid_send_delay_tps);
             }
@@ -164,19 +368,60 @@ impl BenchmarkConfig {
     }
 }
 
-fn spawn_transaction_emitter(rpc: Jsonrpc) -> Sender<TransactionView> {
-    let (sender, receiver) = bounded(1000);
+// Append the result into `metrics.json`, tagged with the run id
+fn record_result(result: &Value) {
+    let mut result = result.clone();
+    result["run_id"] = json!(*RUN_ID);
+    let recorder = METRICS_RECORDER.lock().unwrap();
+    if let Some(mut recorder) = recorder.as_ref() {
+        let _ = recorder.write(result.to_string().as_bytes());
+        let _ = recorder.write(b"\n");
+        let _ = recorder.flush();
+    };
+}
+
+fn spawn_transaction_emitter(
+    endpoint: usize,
+    rpc: Jsonrpc,
+    latency_tracker: LatencyTracker,
+    propagation_tracker: TransactionPropagationTracker,
+    client_monitor: ClientMonitor,
+    send_error_counter: SendErrorCounter,
+) -> Sender<TransactionView> {
+    let (sender, receiver) = bounded(EMITTER_CHANNEL_CAPACITY);
     spawn(move || {
         while let Ok(transaction) = receiver.recv() {
             let transaction: TransactionView = transaction;
             loop {
+                latency_tracker.record_sent(transaction.hash(), endpoint);
                 // Chain reorg will cause many double-spent problem. Just ignore it. The chain
                 // monitor will solve it.
-                if let Err(err) = rpc.send_transaction_result(transaction.data().into()) {
-                    let errs = err.to_string();
-                    if errs.contains("PoolIsFull") || errs.contains("TransactionPoolFull") {
-                        sleep(Duration::from_secs(1));
-                        continue;
+                let send_start = Instant::now();
+                let result = rpc.send_transaction_result(transaction.data().into());
+                client_monitor.record_send_transaction(endpoint, send_start.elapsed());
+                match result {
+                    Ok(_) => {
+                        propagation_tracker.record_accepted(transaction.hash(), endpoint);
+                        send_error_counter.record_accepted(endpoint);
+                        counter!(
+                            "tps_bench.bench.sent_transactions",
+                            1,
+                            "endpoint" => rpc.uri().clone()
+                        );
+                    }
+                    Err(err) => {
+                        latency_tracker.forget(&transaction.hash());
+                        send_error_counter.record_error(endpoint, err.kind);
+                        counter!(
+                            "tps_bench.bench.send_errors",
+                            1,
+                            "endpoint" => rpc.uri().clone(),
+                            "kind" => err.kind.label()
+                        );
+                        if err.kind == SendErrorKind::PoolFull {
+                            sleep(Duration::from_secs(1));
+                            continue;
+                        }
                     }
                 }
                 break;
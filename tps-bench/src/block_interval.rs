@@ -0,0 +1,98 @@
+use rand::distributions::{Distribution, Exp};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde_derive::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// The distribution of the intervals between blocks generated by the dummy miner.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub enum BlockInterval {
+    /// Sleep `block_time` between blocks
+    Fixed,
+    /// Exponentially distributed intervals with `mean` milliseconds, like the real PoW
+    Exponential { mean: u64 },
+    /// Uniformly distributed intervals in `[min, max]` milliseconds
+    Uniform { min: u64, max: u64 },
+    /// Replay the intervals between the block timestamps(in milliseconds, one per line) recorded
+    /// in the file at `path`, e.g. taken from mainnet. It starts over after reaching the end.
+    Trace { path: String },
+}
+
+impl Default for BlockInterval {
+    fn default() -> Self {
+        BlockInterval::Fixed
+    }
+}
+
+pub struct BlockIntervalSampler {
+    block_time: Duration,
+    interval: BlockInterval,
+    rng: StdRng,
+    trace: Vec<Duration>,
+    cursor: usize,
+}
+
+impl BlockIntervalSampler {
+    pub fn new(block_time: Duration, interval: BlockInterval, seed: u64) -> Self {
+        let trace = match interval {
+            BlockInterval::Trace { ref path } => load_trace(path),
+            _ => Vec::new(),
+        };
+        Self {
+            block_time,
+            interval,
+            rng: StdRng::seed_from_u64(seed),
+            trace,
+            cursor: 0,
+        }
+    }
+
+    pub fn next_interval(&mut self) -> Duration {
+        match self.interval {
+            BlockInterval::Fixed => self.block_time,
+            BlockInterval::Exponential { mean } => {
+                let exp = Exp::new(1.0 / mean.max(1) as f64);
+                Duration::from_micros((exp.sample(&mut self.rng) * 1000.0) as u64)
+            }
+            BlockInterval::Uniform { min, max } => {
+                Duration::from_millis(self.rng.gen_range(min, max.max(min) + 1))
+            }
+            BlockInterval::Trace { .. } => {
+                let interval = self.trace[self.cursor];
+                self.cursor = (self.cursor + 1) % self.trace.len();
+                interval
+            }
+        }
+    }
+}
+
+fn load_trace(path: &str) -> Vec<Duration> {
+    let content = std::fs::read_to_string(path).unwrap_or_else(|err| {
+        prompt_and_exit!("BlockInterval::Trace read {} error: {}", path, err);
+    });
+    let timestamps = content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            line.parse::<u64>().unwrap_or_else(|err| {
+                prompt_and_exit!(
+                    "BlockInterval::Trace parse {} {:?} error: {}",
+                    path,
+                    line,
+                    err
+                );
+            })
+        })
+        .collect::<Vec<_>>();
+    if timestamps.len() < 2 {
+        prompt_and_exit!(
+            "BlockInterval::Trace {} should contain at least 2 timestamps",
+            path
+        );
+    }
+    timestamps
+        .windows(2)
+        .map(|pair| Duration::from_millis(pair[1].saturating_sub(pair[0])))
+        .collect()
+}
@@ -0,0 +1,218 @@
+use crate::net::Net;
+use crate::statistics::Percentiles;
+
+use log::warn;
+use rand::Rng;
+use serde_derive::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The capacity of the channel queueing the signed transactions of each emitter.
+pub const EMITTER_CHANNEL_CAPACITY: usize = 1000;
+
+// A stage is considered the bottleneck when the sending loop spends more than this share of its
+// wall time in it, rather than sleeping `send_delay`
+const BOTTLENECK_SHARE: f64 = 0.5;
+// The emitter queues are considered saturated when their median depth exceeds this share of the
+// capacity
+const SATURATED_QUEUE_SHARE: f64 = 0.9;
+// The samples kept per stage to evaluate the percentiles, so that neither the memory nor the cost
+// of `ClientMonitor::metrics` grows with the length of the benchmark
+const RESERVOIR_CAPACITY: usize = 10_000;
+
+/// Measures the bench client itself, to tell whether the client rather than CKB limits a
+/// benchmark: the time the sending loop waits for UTXOs, builds and signs transactions and waits
+/// for a free emitter, and the queue depth and `send_transaction` round-trip time per endpoint.
+#[derive(Clone)]
+pub struct ClientMonitor {
+    inner: Arc<Mutex<ClientMonitorInner>>,
+}
+
+struct ClientMonitorInner {
+    started_at: Instant,
+    utxo_wait_us: Reservoir,
+    sign_us: Reservoir,
+    dispatch_wait_us: Reservoir,
+    endpoints: Vec<EndpointSamples>,
+}
+
+#[derive(Default, Clone)]
+struct EndpointSamples {
+    queue_depth: Reservoir,
+    send_transaction_rtt_us: Reservoir,
+}
+
+/// A uniform random sample of at most `RESERVOIR_CAPACITY` values, along with the exact count,
+/// sum and maximum of all the values.
+#[derive(Default, Clone)]
+struct Reservoir {
+    samples: Vec<u64>,
+    count: u64,
+    sum: u64,
+    max: u64,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ClientMetrics {
+    /// Waiting for the UTXOs to spend, via coin selection
+    utxo_wait_us: Percentiles,
+    /// Building and signing a transaction
+    sign_us: Percentiles,
+    /// Waiting for an emitter whose queue is not full
+    dispatch_wait_us: Percentiles,
+    /// The share of the wall time of the sending loop in each stage above
+    utxo_wait_share: f64,
+    sign_share: f64,
+    dispatch_wait_share: f64,
+    endpoints: Vec<EndpointClientMetrics>,
+    /// The stages which appear to limit the benchmark, in `utxo_supply`, `signing` and
+    /// `emitter_queue`
+    bottlenecks: Vec<String>,
+    /// Whether the client appears to be the bottleneck, so the TPS underestimates CKB
+    client_bottleneck: bool,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct EndpointClientMetrics {
+    endpoint: String,
+    /// The transactions queued in the emitter, of `EMITTER_CHANNEL_CAPACITY`
+    queue_depth: Percentiles,
+    send_transaction_rtt_us: Percentiles,
+}
+
+impl ClientMonitor {
+    pub fn new(endpoints: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(ClientMonitorInner {
+                started_at: Instant::now(),
+                utxo_wait_us: Reservoir::default(),
+                sign_us: Reservoir::default(),
+                dispatch_wait_us: Reservoir::default(),
+                endpoints: vec![EndpointSamples::default(); endpoints],
+            })),
+        }
+    }
+
+    pub fn record_utxo_wait(&self, duration: Duration) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.utxo_wait_us.push(duration.as_micros() as u64);
+    }
+
+    pub fn record_sign(&self, duration: Duration) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.sign_us.push(duration.as_micros() as u64);
+    }
+
+    pub fn record_dispatch(&self, wait: Duration, endpoint: usize, queue_depth: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.dispatch_wait_us.push(wait.as_micros() as u64);
+        inner.endpoints[endpoint]
+            .queue_depth
+            .push(queue_depth as u64);
+    }
+
+    pub fn record_send_transaction(&self, endpoint: usize, rtt: Duration) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.endpoints[endpoint]
+            .send_transaction_rtt_us
+            .push(rtt.as_micros() as u64);
+    }
+
+    /// Evaluate the metrics, and log a warning if the client appears to be the bottleneck.
+    pub fn metrics(&self, net: &Net) -> ClientMetrics {
+        // Sort outside of the lock, which the sending loop and the emitters wait for
+        let (elapsed, utxo_wait_us, sign_us, dispatch_wait_us, endpoint_samples) = {
+            let inner = self.inner.lock().unwrap();
+            (
+                inner.started_at.elapsed(),
+                inner.utxo_wait_us.clone(),
+                inner.sign_us.clone(),
+                inner.dispatch_wait_us.clone(),
+                inner.endpoints.clone(),
+            )
+        };
+        let elapsed_us = (elapsed.as_micros() as f64).max(1.0);
+        let share = |reservoir: &Reservoir| reservoir.sum as f64 / elapsed_us;
+        let utxo_wait_share = share(&utxo_wait_us);
+        let sign_share = share(&sign_us);
+        let dispatch_wait_share = share(&dispatch_wait_us);
+        let endpoints = net
+            .endpoints()
+            .iter()
+            .zip(endpoint_samples.iter())
+            .map(|(rpc, samples)| EndpointClientMetrics {
+                endpoint: rpc.uri().clone(),
+                queue_depth: samples.queue_depth.percentiles(),
+                send_transaction_rtt_us: samples.send_transaction_rtt_us.percentiles(),
+            })
+            .collect::<Vec<_>>();
+
+        let saturated_queue_depth =
+            (EMITTER_CHANNEL_CAPACITY as f64 * SATURATED_QUEUE_SHARE) as u64;
+        let mut bottlenecks = Vec::new();
+        if utxo_wait_share > BOTTLENECK_SHARE {
+            bottlenecks.push("utxo_supply".to_owned());
+        }
+        if sign_share > BOTTLENECK_SHARE {
+            bottlenecks.push("signing".to_owned());
+        }
+        if dispatch_wait_share > BOTTLENECK_SHARE
+            || endpoints
+                .iter()
+                .any(|endpoint| endpoint.queue_depth.p50 >= saturated_queue_depth)
+        {
+            bottlenecks.push("emitter_queue".to_owned());
+        }
+
+        let metrics = ClientMetrics {
+            utxo_wait_us: utxo_wait_us.percentiles(),
+            sign_us: sign_us.percentiles(),
+            dispatch_wait_us: dispatch_wait_us.percentiles(),
+            utxo_wait_share,
+            sign_share,
+            dispatch_wait_share,
+            endpoints,
+            client_bottleneck: !bottlenecks.is_empty(),
+            bottlenecks,
+        };
+        if metrics.client_bottleneck {
+            let message = format!(
+                "[CLIENT BOTTLENECK] the bench client appears to limit this benchmark at {:?}, \
+                 the TPS may underestimate CKB: utxo_wait_share={:.2}, sign_share={:.2}, \
+                 dispatch_wait_share={:.2}",
+                metrics.bottlenecks,
+                metrics.utxo_wait_share,
+                metrics.sign_share,
+                metrics.dispatch_wait_share,
+            );
+            eprintln!("{}", message);
+            warn!("{}", message);
+        }
+        metrics
+    }
+}
+
+impl Reservoir {
+    fn push(&mut self, value: u64) {
+        self.count += 1;
+        self.sum += value;
+        self.max = self.max.max(value);
+        if self.samples.len() < RESERVOIR_CAPACITY {
+            self.samples.push(value);
+        } else {
+            // Algorithm R: keep the value with the probability `RESERVOIR_CAPACITY / count`
+            let index = rand::thread_rng().gen_range(0, self.count) as usize;
+            if index < RESERVOIR_CAPACITY {
+                self.samples[index] = value;
+            }
+        }
+    }
+
+    fn percentiles(&self) -> Percentiles {
+        Percentiles {
+            count: self.count,
+            max: self.max,
+            ..Percentiles::new(&self.samples)
+        }
+    }
+}
@@ -0,0 +1,284 @@
+use crate::utxo::UTXO;
+use crossbeam_channel::Receiver;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use serde_derive::{Deserialize, Serialize};
+
+/// The strategy used to pick the inputs of a benchmark transaction.
+///
+/// Strategies other than `Greedy` buffer up to `buffer` UTXOs from the channel and select among
+/// them, so that the input shape and the change are controlled variables of a benchmark.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+pub enum CoinSelection {
+    /// Take UTXOs in channel order until the target capacity is met.
+    Greedy,
+    /// Take exactly `inputs` UTXOs in channel order. If they don't cover the target capacity,
+    /// more UTXOs are appended until it is met.
+    ExactCount { inputs: usize },
+    /// Take the smallest buffered UTXOs first.
+    SmallestFirst { buffer: usize },
+    /// Take the largest buffered UTXOs first.
+    LargestFirst { buffer: usize },
+    /// Take buffered UTXOs in a random order, which is reproducible by `seed`.
+    Random { buffer: usize, seed: u64 },
+    /// Search the buffered UTXOs for a set whose total capacity is in
+    /// `[target, target + tolerance]`, giving up after `tries` steps and falling back to
+    /// `LargestFirst`.
+    BranchAndBound {
+        buffer: usize,
+        tolerance: u64,
+        tries: usize,
+    },
+}
+
+impl Default for CoinSelection {
+    fn default() -> Self {
+        CoinSelection::Greedy
+    }
+}
+
+pub struct CoinSelector {
+    strategy: CoinSelection,
+    pool: Vec<UTXO>,
+    rng: StdRng,
+}
+
+impl CoinSelector {
+    pub fn new(strategy: CoinSelection) -> Self {
+        let seed = match strategy {
+            CoinSelection::Random { seed, .. } => seed,
+            _ => 0,
+        };
+        Self {
+            strategy,
+            pool: Vec::new(),
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Select inputs whose total capacity is not less than `target`. Return `None` when
+    /// `utxo_rx` is disconnected before enough capacity is collected.
+    pub fn select(&mut self, utxo_rx: &Receiver<UTXO>, target: u64) -> Option<Vec<UTXO>> {
+        match self.strategy {
+            CoinSelection::Greedy => self.select_in_order(utxo_rx, target, 0),
+            CoinSelection::ExactCount { inputs } => self.select_in_order(utxo_rx, target, inputs),
+            CoinSelection::SmallestFirst { buffer } => {
+                self.fill(utxo_rx, buffer, target)?;
+                self.pool.sort_by_key(|utxo| utxo.capacity());
+                Some(self.take_until(target))
+            }
+            CoinSelection::LargestFirst { buffer } => {
+                self.fill(utxo_rx, buffer, target)?;
+                self.pool
+                    .sort_by_key(|utxo| std::cmp::Reverse(utxo.capacity()));
+                Some(self.take_until(target))
+            }
+            CoinSelection::Random { buffer, .. } => {
+                self.fill(utxo_rx, buffer, target)?;
+                self.pool.shuffle(&mut self.rng);
+                Some(self.take_until(target))
+            }
+            CoinSelection::BranchAndBound {
+                buffer,
+                tolerance,
+                tries,
+            } => {
+                self.fill(utxo_rx, buffer, target)?;
+                self.pool
+                    .sort_by_key(|utxo| std::cmp::Reverse(utxo.capacity()));
+                match branch_and_bound(&self.pool, target, tolerance, tries) {
+                    Some(selected) => Some(self.take_indexes(selected)),
+                    None => Some(self.take_until(target)),
+                }
+            }
+        }
+    }
+
+    fn select_in_order(
+        &mut self,
+        utxo_rx: &Receiver<UTXO>,
+        target: u64,
+        min_inputs: usize,
+    ) -> Option<Vec<UTXO>> {
+        let mut inputs = self.pool.split_off(0);
+        let mut total_capacity = inputs.iter().map(|utxo| utxo.capacity()).sum::<u64>();
+        while total_capacity < target || inputs.len() < min_inputs {
+            let utxo = utxo_rx.recv().ok()?;
+            total_capacity += utxo.capacity();
+            inputs.push(utxo);
+        }
+        Some(inputs)
+    }
+
+    // Fill the pool up to `buffer` UTXOs, or more if the buffered capacity is less than `target`.
+    // Blocks only when the buffered capacity is not enough.
+    fn fill(&mut self, utxo_rx: &Receiver<UTXO>, buffer: usize, target: u64) -> Option<()> {
+        let mut total_capacity = self.pool.iter().map(|utxo| utxo.capacity()).sum::<u64>();
+        while total_capacity < target {
+            let utxo = utxo_rx.recv().ok()?;
+            total_capacity += utxo.capacity();
+            self.pool.push(utxo);
+        }
+        while self.pool.len() < buffer {
+            match utxo_rx.try_recv() {
+                Ok(utxo) => self.pool.push(utxo),
+                Err(_) => break,
+            }
+        }
+        Some(())
+    }
+
+    fn take_until(&mut self, target: u64) -> Vec<UTXO> {
+        let mut total_capacity = 0;
+        let count = self
+            .pool
+            .iter()
+            .take_while(|utxo| {
+                let enough = total_capacity >= target;
+                total_capacity += utxo.capacity();
+                !enough
+            })
+            .count();
+        self.pool.drain(0..count).collect()
+    }
+
+    fn take_indexes(&mut self, mut indexes: Vec<usize>) -> Vec<UTXO> {
+        indexes.sort_unstable_by(|a, b| b.cmp(a));
+        indexes
+            .into_iter()
+            .map(|index| self.pool.swap_remove(index))
+            .collect()
+    }
+}
+
+// Depth-first search over the `pool`, which is sorted by capacity descending. Return the indexes
+// of the selected UTXOs.
+fn branch_and_bound(
+    pool: &[UTXO],
+    target: u64,
+    tolerance: u64,
+    tries: usize,
+) -> Option<Vec<usize>> {
+    let capacities = pool.iter().map(|utxo| utxo.capacity()).collect::<Vec<_>>();
+    let mut remaining = capacities.iter().sum::<u64>();
+    if remaining < target {
+        return None;
+    }
+
+    let upper = target.saturating_add(tolerance);
+    let (mut selected, mut total) = (Vec::<usize>::new(), 0u64);
+    let mut best: Option<(u64, Vec<usize>)> = None;
+    let mut index = 0;
+    for _ in 0..tries {
+        let backtrack = if total > upper || total + remaining < target {
+            true
+        } else if total >= target {
+            if best
+                .as_ref()
+                .map(|(waste, _)| total - target < *waste)
+                .unwrap_or(true)
+            {
+                best = Some((total - target, selected.clone()));
+            }
+            if total == target {
+                break;
+            }
+            true
+        } else {
+            index >= capacities.len()
+        };
+
+        if backtrack {
+            // Restore the capacities of the excluded UTXOs between the last selected one and
+            // `index`, then exclude the last selected one.
+            while index > selected.last().map(|last| last + 1).unwrap_or(0) {
+                index -= 1;
+                remaining += capacities[index];
+            }
+            match selected.pop() {
+                Some(last) => {
+                    total -= capacities[last];
+                    index = last + 1;
+                }
+                None => break,
+            }
+        } else {
+            remaining -= capacities[index];
+            total += capacities[index];
+            selected.push(index);
+            index += 1;
+        }
+    }
+
+    best.map(|(_, selected)| selected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ckb_types::packed::{CellOutput, OutPoint};
+    use ckb_types::prelude::*;
+    use crossbeam_channel::unbounded;
+
+    fn utxos(capacities: &[u64]) -> Vec<UTXO> {
+        capacities
+            .iter()
+            .enumerate()
+            .map(|(index, capacity)| {
+                let output = CellOutput::new_builder().capacity(capacity.pack()).build();
+                let out_point = OutPoint::new_builder().index(index.pack()).build();
+                UTXO::new(output, out_point)
+            })
+            .collect()
+    }
+
+    fn select(strategy: CoinSelection, capacities: &[u64], target: u64) -> Vec<u64> {
+        let (utxo_tx, utxo_rx) = unbounded();
+        for utxo in utxos(capacities) {
+            utxo_tx.send(utxo).unwrap();
+        }
+        let mut selected = CoinSelector::new(strategy)
+            .select(&utxo_rx, target)
+            .expect("enough capacity")
+            .iter()
+            .map(UTXO::capacity)
+            .collect::<Vec<_>>();
+        selected.sort_unstable();
+        selected
+    }
+
+    #[test]
+    fn test_branch_and_bound_exact_match() {
+        let pool = utxos(&[5, 4, 3, 2]);
+        assert_eq!(branch_and_bound(&pool, 7, 0, 100), Some(vec![0, 3]));
+    }
+
+    #[test]
+    fn test_branch_and_bound_no_solution() {
+        // Every subset overshoots `[7, 8]`
+        assert_eq!(branch_and_bound(&utxos(&[10, 10]), 7, 1, 100), None);
+        // Not enough capacity at all
+        assert_eq!(branch_and_bound(&utxos(&[3]), 5, 0, 100), None);
+    }
+
+    #[test]
+    fn test_select_exact_match() {
+        let strategy = CoinSelection::BranchAndBound {
+            buffer: 4,
+            tolerance: 0,
+            tries: 100,
+        };
+        assert_eq!(select(strategy, &[5, 4, 3, 2], 7), vec![2, 5]);
+    }
+
+    #[test]
+    fn test_select_falls_back_to_largest_first() {
+        let strategy = CoinSelection::BranchAndBound {
+            buffer: 2,
+            tolerance: 1,
+            tries: 100,
+        };
+        assert_eq!(select(strategy, &[10, 10], 7), vec![10]);
+    }
+}
@@ -1,14 +1,81 @@
 use crate::config::{Config, Spec, Url};
+use crate::metric::{BlockBound, MetricOptions};
+use crate::periodicity::default_proposal_window;
+use crate::report::ReportFormat;
+use crate::sweep::DEFAULT_MAX_TRANSACTION_BYTES;
+use ckb_types::H256;
+use std::path::PathBuf;
+use std::str::FromStr;
 
 pub const MINE_SUBCOMMAND: &str = "mine";
 pub const BENCH_SUBCOMMAND: &str = "bench";
 pub const METRICS_SUBCOMMAND: &str = "metric";
+pub const ACCOUNT_SUBCOMMAND: &str = "account";
+pub const SWEEP_SUBCOMMAND: &str = "sweep";
+pub const COMPARE_SUBCOMMAND: &str = "compare";
+pub const REPORT_SUBCOMMAND: &str = "report";
 
 #[derive(Debug, Clone)]
 pub enum CommandLine {
     MineMode(Config, u64 /* blocks */),
     BenchMode(Config, bool),
-    MetricMode(Vec<Url>),
+    MetricMode(Vec<Url>, MetricOptions),
+    AccountMode(
+        Config,
+        Vec<String>, /* private keys */
+        Vec<H256>,   /* lock hashes */
+        OutputFormat,
+    ),
+    SweepMode(
+        Config,
+        String, /* account, bencher or miner */
+        usize,  /* cells */
+        u64,    /* max transaction bytes */
+    ),
+    CompareMode(
+        PathBuf, /* baseline metrics.json */
+        PathBuf, /* candidate metrics.json */
+        f64,     /* tolerance */
+        OutputFormat,
+    ),
+    ReportMode(
+        PathBuf, /* data directory */
+        ReportFormat,
+        Option<PathBuf>, /* output file */
+    ),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum OutputFormat {
+    Table,
+    Json,
+    Csv,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(OutputFormat::Table),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            _ => Err(format!("unsupported output format: {}", s)),
+        }
+    }
+}
+
+fn parse_h256(s: &str) -> Result<H256, String> {
+    H256::from_str(s.trim_start_matches("0x")).map_err(|err| err.to_string())
+}
+
+fn parse_proposal_window(s: &str) -> Result<(u64, u64), String> {
+    let mut numbers = s.split(',').map(|number| number.trim().parse::<u64>());
+    match (numbers.next(), numbers.next(), numbers.next()) {
+        (Some(Ok(closest)), Some(Ok(farthest)), None) if closest <= farthest => {
+            Ok((closest, farthest))
+        }
+        _ => Err(format!("invalid proposal window: {}", s)),
+    }
 }
 
 pub fn commandline() -> CommandLine {
@@ -60,14 +127,198 @@ pub fn commandline() -> CommandLine {
         )
         .subcommand(
             clap::SubCommand::with_name(METRICS_SUBCOMMAND)
-                .about("Caculate tps metrics in a specify blocks window")
+                .about(
+                    "Caculate tps metrics in a specify blocks window\n\
+                     Example:\n\
+                     tps-bench metric --rpc-urls http://127.0.0.1:8114 --from 1000 --to 2000 \
+                     --window 20 --format csv",
+                )
+                .arg(
+                    clap::Arg::from_usage("--rpc-urls <ENDPOINTS> 'the ckb rpc endpoints'")
+                        .required(true)
+                        .multiple(true)
+                        .validator(|s| Url::parse(&s).map(|_| ()).map_err(|err| err.to_string())),
+                )
+                .arg(clap::Arg::from_usage(
+                    "[periodicity] --periodicity 'analyze the periodicity of per-block transaction counts'",
+                ))
+                .arg(
+                    clap::Arg::from_usage(
+                        "--proposal-window [CLOSEST,FARTHEST] 'the proposal window of the chain \
+                         to compare the periodicity with, the default consensus by default'",
+                    )
+                    .validator(|s| parse_proposal_window(&s).map(|_| ())),
+                )
+                .arg(
+                    clap::Arg::from_usage(
+                        "--from [BOUND] 'the first block, a block number or time:<unix milliseconds>, 1 by default'",
+                    )
+                    .validator(|s| s.parse::<BlockBound>().map(|_| ())),
+                )
+                .arg(
+                    clap::Arg::from_usage(
+                        "--to [BOUND] 'the last block, a block number or time:<unix milliseconds>, the confirmed tip by default'",
+                    )
+                    .validator(|s| s.parse::<BlockBound>().map(|_| ())),
+                )
+                .arg(clap::Arg::from_usage(
+                    "[per-block] --per-block 'output the breakdown of every block'",
+                ))
+                .arg(
+                    clap::Arg::from_usage(
+                        "--window [BLOCKS] 'output the TPS series of the sliding window of BLOCKS blocks'",
+                    )
+                    .validator(|s| {
+                        s.parse::<u64>()
+                            .map_err(|err| err.to_string())
+                            .and_then(|blocks| {
+                                if blocks == 0 {
+                                    Err("window should be greater than 0".to_owned())
+                                } else {
+                                    Ok(())
+                                }
+                            })
+                    }),
+                )
+                .arg(
+                    clap::Arg::from_usage("--format [FORMAT] 'the output format'")
+                        .possible_values(&["table", "json", "csv"])
+                        .default_value("table"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name(ACCOUNT_SUBCOMMAND)
+                .about(
+                    "Report the live capacity and cells distribution of accounts\n\
+                     Reports the bencher and miner configured in spec if neither --private-keys \
+                     nor --lock-hashes is given\n\
+                     Example:\n\
+                     tps-bench account -s dev --rpc-urls http://127.0.0.1:8114 --format json",
+                )
+                .arg(clap::Arg::from_usage(
+                    "-s, --spec <FILE> 'the spec: staging, dev, release or path to spec file'",
+                ))
+                .arg(
+                    clap::Arg::from_usage("--rpc-urls <ENDPOINTS> 'the ckb rpc endpoints'")
+                        .required(true)
+                        .multiple(true)
+                        .validator(|s| Url::parse(&s).map(|_| ()).map_err(|err| err.to_string())),
+                )
+                .arg(
+                    clap::Arg::from_usage(
+                        "--private-keys [KEYS] 'the private keys of the accounts to report'",
+                    )
+                    .multiple(true),
+                )
+                .arg(
+                    clap::Arg::from_usage(
+                        "--lock-hashes [HASHES] 'the lock hashes to report'",
+                    )
+                    .multiple(true)
+                    .validator(|s| parse_h256(&s).map(|_| ())),
+                )
+                .arg(
+                    clap::Arg::from_usage("--format [FORMAT] 'the output format'")
+                        .possible_values(&["table", "json", "csv"])
+                        .default_value("table"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name(SWEEP_SUBCOMMAND)
+                .about(
+                    "Merge the matured cells of the configured account into the given number of cells\n\
+                     Example:\n\
+                     tps-bench sweep -s dev --rpc-urls http://127.0.0.1:8114 --cells 100",
+                )
+                .arg(clap::Arg::from_usage(
+                    "-s, --spec <FILE> 'the spec: staging, dev, release or path to spec file'",
+                ))
                 .arg(
                     clap::Arg::from_usage("--rpc-urls <ENDPOINTS> 'the ckb rpc endpoints'")
                         .required(true)
                         .multiple(true)
                         .validator(|s| Url::parse(&s).map(|_| ()).map_err(|err| err.to_string())),
+                )
+                .arg(
+                    clap::Arg::from_usage("--cells <NUMBER> 'the number of cells to merge into'")
+                        .required(true)
+                        .validator(|s| {
+                            s.parse::<usize>()
+                                .map_err(|err| err.to_string())
+                                .and_then(|cells| {
+                                    if cells == 0 {
+                                        Err("cells should be greater than 0".to_owned())
+                                    } else {
+                                        Ok(())
+                                    }
+                                })
+                        }),
+                )
+                .arg(
+                    clap::Arg::from_usage("--account [ACCOUNT] 'the account to sweep'")
+                        .possible_values(&["bencher", "miner"])
+                        .default_value("bencher"),
+                )
+                .arg(
+                    clap::Arg::from_usage(
+                        "--max-tx-bytes [BYTES] 'the serialized size limit of a transaction, \
+                         500000 by default'",
+                    )
+                    .validator(|s| s.parse::<u64>().map(|_| ()).map_err(|err| err.to_string())),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name(COMPARE_SUBCOMMAND)
+                .about(
+                    "Compare the benchmark results of a candidate against a baseline, matched by \
+                     the benchmark parameters, and exit with 1 if any regression exceeds the tolerance\n\
+                     Example:\n\
+                     tps-bench compare --baseline base/metrics.json --candidate tpsbench/metrics.json \
+                     --tolerance 5",
+                )
+                .arg(
+                    clap::Arg::from_usage("--baseline <FILE> 'the baseline metrics.json'")
+                        .required(true),
+                )
+                .arg(
+                    clap::Arg::from_usage("--candidate <FILE> 'the candidate metrics.json'")
+                        .required(true),
+                )
+                .arg(
+                    clap::Arg::from_usage(
+                        "--tolerance [PERCENT] 'the tolerated regression in percent of the baseline'",
+                    )
+                    .default_value("5")
+                    .validator(|s| s.parse::<f64>().map(|_| ()).map_err(|err| err.to_string())),
+                )
+                .arg(
+                    clap::Arg::from_usage("--format [FORMAT] 'the output format'")
+                        .possible_values(&["table", "json", "csv"])
+                        .default_value("table"),
                 ),
         )
+        .subcommand(
+            clap::SubCommand::with_name(REPORT_SUBCOMMAND)
+                .about(
+                    "Render a self-contained report of a run from its data directory, including the \
+                     configuration, node versions, benchmark results, TPS and tx pool charts and \
+                     the best TPS search\n\
+                     Example:\n\
+                     tps-bench report --data-dir tpsbench --format html --output report.html",
+                )
+                .arg(
+                    clap::Arg::from_usage("--data-dir <DIR> 'the data directory of the run'")
+                        .required(true),
+                )
+                .arg(
+                    clap::Arg::from_usage("--format [FORMAT] 'the report format'")
+                        .possible_values(&["markdown", "html"])
+                        .default_value("markdown"),
+                )
+                .arg(clap::Arg::from_usage(
+                    "--output [FILE] 'write the report into the file, stdout by default'",
+                )),
+        )
         .get_matches();
 
     match matches.subcommand() {
@@ -119,7 +370,146 @@ pub fn commandline() -> CommandLine {
                 .expect("clap arg option `required(true)` checked")
                 .map(|str| Url::parse(str).expect("clap arg option `validator` checked"))
                 .collect::<Vec<_>>();
-            CommandLine::MetricMode(rpc_urls)
+            let periodicity = if options.is_present("periodicity") {
+                let proposal_window = options
+                    .value_of("proposal-window")
+                    .map(|str| {
+                        parse_proposal_window(str).expect("clap arg option `validator` checked")
+                    })
+                    .unwrap_or_else(default_proposal_window);
+                Some(proposal_window)
+            } else {
+                None
+            };
+            let from = options
+                .value_of("from")
+                .map(|str| str.parse().expect("clap arg option `validator` checked"));
+            let to = options
+                .value_of("to")
+                .map(|str| str.parse().expect("clap arg option `validator` checked"));
+            let window = options.value_of("window").map(|str| {
+                str.parse::<u64>()
+                    .expect("clap arg option `validator` checked")
+            });
+            let format = options
+                .value_of("format")
+                .expect("clap arg option `default_value` checked")
+                .parse::<OutputFormat>()
+                .expect("clap arg option `possible_values` checked");
+            let metric_options = MetricOptions {
+                from,
+                to,
+                per_block: options.is_present("per-block"),
+                window,
+                periodicity,
+                format,
+            };
+            CommandLine::MetricMode(rpc_urls, metric_options)
+        }
+        (ACCOUNT_SUBCOMMAND, Some(options)) => {
+            let spec = {
+                let filepath = options
+                    .value_of("spec")
+                    .expect("clap arg option `required(true)` checked");
+                match Spec::load(filepath) {
+                    Ok(spec) => spec,
+                    Err(err) => prompt_and_exit!("Spec::load({}) error: {:?}", filepath, err),
+                }
+            };
+            let rpc_urls = options
+                .values_of("rpc-urls")
+                .expect("clap arg option `required(true)` checked")
+                .map(|str| Url::parse(str).expect("clap arg option `validator` checked"))
+                .collect::<Vec<_>>();
+            let private_keys = options
+                .values_of("private-keys")
+                .map(|values| values.map(ToOwned::to_owned).collect::<Vec<_>>())
+                .unwrap_or_default();
+            let lock_hashes = options
+                .values_of("lock-hashes")
+                .map(|values| {
+                    values
+                        .map(|str| parse_h256(str).expect("clap arg option `validator` checked"))
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default();
+            let format = options
+                .value_of("format")
+                .expect("clap arg option `default_value` checked")
+                .parse::<OutputFormat>()
+                .expect("clap arg option `possible_values` checked");
+            let config = Config::new(spec, rpc_urls);
+            CommandLine::AccountMode(config, private_keys, lock_hashes, format)
+        }
+        (SWEEP_SUBCOMMAND, Some(options)) => {
+            let spec = {
+                let filepath = options
+                    .value_of("spec")
+                    .expect("clap arg option `required(true)` checked");
+                match Spec::load(filepath) {
+                    Ok(spec) => spec,
+                    Err(err) => prompt_and_exit!("Spec::load({}) error: {:?}", filepath, err),
+                }
+            };
+            let rpc_urls = options
+                .values_of("rpc-urls")
+                .expect("clap arg option `required(true)` checked")
+                .map(|str| Url::parse(str).expect("clap arg option `validator` checked"))
+                .collect::<Vec<_>>();
+            let cells = options
+                .value_of("cells")
+                .expect("clap arg option `required(true)` checked")
+                .parse::<usize>()
+                .expect("clap arg option `validator` checked");
+            let account = options
+                .value_of("account")
+                .expect("clap arg option `default_value` checked")
+                .to_owned();
+            let max_transaction_bytes = options
+                .value_of("max-tx-bytes")
+                .map(|str| {
+                    str.parse::<u64>()
+                        .expect("clap arg option `validator` checked")
+                })
+                .unwrap_or(DEFAULT_MAX_TRANSACTION_BYTES);
+            let config = Config::new(spec, rpc_urls);
+            CommandLine::SweepMode(config, account, cells, max_transaction_bytes)
+        }
+        (COMPARE_SUBCOMMAND, Some(options)) => {
+            let baseline = options
+                .value_of("baseline")
+                .expect("clap arg option `required(true)` checked");
+            let candidate = options
+                .value_of("candidate")
+                .expect("clap arg option `required(true)` checked");
+            let tolerance = options
+                .value_of("tolerance")
+                .expect("clap arg option `default_value` checked")
+                .parse::<f64>()
+                .expect("clap arg option `validator` checked");
+            let format = options
+                .value_of("format")
+                .expect("clap arg option `default_value` checked")
+                .parse::<OutputFormat>()
+                .expect("clap arg option `possible_values` checked");
+            CommandLine::CompareMode(
+                PathBuf::from(baseline),
+                PathBuf::from(candidate),
+                tolerance / 100.0,
+                format,
+            )
+        }
+        (REPORT_SUBCOMMAND, Some(options)) => {
+            let data_dir = options
+                .value_of("data-dir")
+                .expect("clap arg option `required(true)` checked");
+            let format = options
+                .value_of("format")
+                .expect("clap arg option `default_value` checked")
+                .parse::<ReportFormat>()
+                .expect("clap arg option `possible_values` checked");
+            let output = options.value_of("output").map(PathBuf::from);
+            CommandLine::ReportMode(PathBuf::from(data_dir), format, output)
         }
         (subcommand, options) => {
             prompt_and_exit!(
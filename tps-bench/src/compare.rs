@@ -0,0 +1,208 @@
+use crate::command::OutputFormat;
+use crate::metric::print_rows;
+use crate::statistics::{is_significant, Summary};
+
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    HigherIsBetter,
+    LowerIsBetter,
+    // Reported but never counted as a regression
+    Neutral,
+}
+
+// The compared metrics: name, JSON pointer into a result line, and direction
+const COMPARED_METRICS: &[(&str, &str, Direction)] = &[
+    ("tps", "/metrics/tps", Direction::HigherIsBetter),
+    (
+        "confirmation_latency_ms.p50",
+        "/latency/confirmation_latency_ms/p50",
+        Direction::LowerIsBetter,
+    ),
+    (
+        "confirmation_latency_ms.p90",
+        "/latency/confirmation_latency_ms/p90",
+        Direction::LowerIsBetter,
+    ),
+    (
+        "confirmation_latency_ms.p99",
+        "/latency/confirmation_latency_ms/p99",
+        Direction::LowerIsBetter,
+    ),
+    (
+        "average_block_time_ms",
+        "/metrics/average_block_time_ms",
+        Direction::Neutral,
+    ),
+];
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Comparison {
+    benchmark: String,
+    metric: String,
+    baseline: Summary,
+    candidate: Summary,
+    delta: f64,
+    /// `delta / baseline.mean`
+    relative_delta: f64,
+    /// `None` if either side has less than 2 samples
+    significant: Option<bool>,
+    regression: bool,
+}
+
+/// Compare the benchmark results in the `metrics.json` of `candidate` against `baseline`,
+/// matched by the benchmark parameters. A metric regresses when it gets worse than `tolerance`,
+/// a ratio of the baseline. Also return the baseline benchmarks missing from `candidate`.
+pub fn compare(
+    baseline: &Path,
+    candidate: &Path,
+    tolerance: f64,
+) -> (Vec<Comparison>, Vec<String>) {
+    let baseline = load_results(baseline);
+    let candidate = load_results(candidate);
+    let missing = baseline
+        .keys()
+        .filter(|key| !candidate.contains_key(*key))
+        .cloned()
+        .collect::<Vec<_>>();
+    for benchmark in candidate.keys().filter(|key| !baseline.contains_key(*key)) {
+        eprintln!("benchmark only in candidate: {}", benchmark);
+    }
+
+    let mut comparisons = Vec::new();
+    for (benchmark, baseline_results) in baseline.iter() {
+        let candidate_results = match candidate.get(benchmark) {
+            Some(candidate_results) => candidate_results,
+            None => continue,
+        };
+        for (metric, pointer, direction) in COMPARED_METRICS.iter() {
+            let baseline = summarize(baseline_results, pointer);
+            let candidate = summarize(candidate_results, pointer);
+            if baseline.count == 0 || candidate.count == 0 {
+                continue;
+            }
+
+            let delta = candidate.mean - baseline.mean;
+            let relative_delta = if baseline.mean.abs() <= std::f64::EPSILON {
+                0.0
+            } else {
+                delta / baseline.mean
+            };
+            let regression = match direction {
+                Direction::HigherIsBetter => relative_delta < -tolerance,
+                Direction::LowerIsBetter => relative_delta > tolerance,
+                Direction::Neutral => false,
+            };
+            comparisons.push(Comparison {
+                benchmark: benchmark.clone(),
+                metric: (*metric).to_owned(),
+                significant: is_significant(&baseline, &candidate),
+                baseline,
+                candidate,
+                delta,
+                relative_delta,
+                regression,
+            });
+        }
+    }
+    (comparisons, missing)
+}
+
+impl Comparison {
+    pub fn is_regression(&self) -> bool {
+        self.regression
+    }
+}
+
+pub fn print_comparisons(format: OutputFormat, comparisons: &[Comparison]) {
+    if let OutputFormat::Json = format {
+        println!("{}", json!(comparisons));
+        return;
+    }
+
+    let headers = [
+        "benchmark",
+        "metric",
+        "baseline",
+        "candidate",
+        "delta",
+        "significant",
+        "regression",
+    ]
+    .iter()
+    .map(|header| (*header).to_owned())
+    .collect::<Vec<_>>();
+    let describe = |summary: &Summary| {
+        format!(
+            "{:.2} ± {:.2} (n={})",
+            summary.mean, summary.standard_deviation, summary.count
+        )
+    };
+    let rows = comparisons
+        .iter()
+        .map(|comparison| {
+            vec![
+                comparison.benchmark.clone(),
+                comparison.metric.clone(),
+                describe(&comparison.baseline),
+                describe(&comparison.candidate),
+                format!(
+                    "{:+.2} ({:+.2}%)",
+                    comparison.delta,
+                    comparison.relative_delta * 100.0
+                ),
+                match comparison.significant {
+                    Some(true) => "yes".to_owned(),
+                    Some(false) => "no".to_owned(),
+                    None => "n/a".to_owned(),
+                },
+                comparison.regression.to_string(),
+            ]
+        })
+        .collect::<Vec<_>>();
+    print_rows(format, "comparisons", &headers, &rows);
+}
+
+// Load the result lines of every repetition in `metrics.json`, grouped by the benchmark
+// parameters. The statistics lines are skipped since they are derived from the results.
+fn load_results(path: &Path) -> BTreeMap<String, Vec<Value>> {
+    let content = std::fs::read_to_string(path).unwrap_or_else(|err| {
+        prompt_and_exit!("read {} error: {}", path.to_string_lossy(), err);
+    });
+    let mut results: BTreeMap<String, Vec<Value>> = BTreeMap::new();
+    for (index, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let result: Value = serde_json::from_str(line).unwrap_or_else(|err| {
+            prompt_and_exit!(
+                "parse {}:{} error: {}",
+                path.to_string_lossy(),
+                index + 1,
+                err
+            );
+        });
+        if result.get("metrics").is_none() {
+            continue;
+        }
+        let benchmark = result
+            .get("benchmark")
+            .map(|benchmark| benchmark.to_string())
+            .unwrap_or_default();
+        results.entry(benchmark).or_default().push(result);
+    }
+    results
+}
+
+fn summarize(results: &[Value], pointer: &str) -> Summary {
+    let samples = results
+        .iter()
+        .filter_map(|result| result.pointer(pointer))
+        .filter_map(Value::as_f64)
+        .collect::<Vec<_>>();
+    Summary::new(&samples)
+}
@@ -1,8 +1,11 @@
-use crate::global::{CELLBASE_MATURITY, CONFIRMATION_BLOCKS, METHOD_TO_EVAL_NET_STABLE};
+use crate::global::{
+    CELLBASE_MATURITY, CONFIRMATION_BLOCKS, METHOD_TO_EVAL_NET_STABLE, RPC_AUTH, RPC_RETRY_POLICY,
+};
 
 use crate::benchmark::BenchmarkConfig;
-use crate::miner::MinerConfig;
+use crate::miner::{CompetingMinerConfig, MinerConfig};
 use crate::net_monitor::MethodToEvalNetStable;
+use crate::rpc::{EndpointAuth, RetryPolicy};
 use serde_derive::{Deserialize, Serialize};
 use std::fs::create_dir_all;
 use std::ops::Deref;
@@ -12,6 +15,9 @@ pub const STAGING_SPEC: &str = include_str!("../specs/staging.toml");
 pub const DEV_SPEC: &str = include_str!("../specs/dev.toml");
 pub const RELEASE_SPEC: &str = include_str!("../specs/release.toml");
 
+// Replaces the credentials in the logged configuration
+const REDACTED: &str = "******";
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Config {
     spec: Spec,
@@ -26,9 +32,21 @@ pub struct Spec {
     pub miner: MinerConfig,
     pub benchmarks: Vec<BenchmarkConfig>,
 
+    /// When not empty, these miners generate blocks during benchmarks instead of `miner`
+    #[serde(default)]
+    pub competing_miners: Vec<CompetingMinerConfig>,
+
     #[serde(default)]
     pub metrics_url: Option<String>,
 
+    /// How the RPC reads are retried after transport errors
+    #[serde(default)]
+    pub rpc_retry: RetryPolicy,
+
+    /// The bearer tokens and HTTP headers of the endpoints
+    #[serde(default)]
+    pub rpc_auth: Vec<EndpointAuth>,
+
     pub consensus_cellbase_maturity: u64,
     pub confirmation_blocks: u64,
     pub ensure_matured_capacity_greater_than: u64,
@@ -67,6 +85,31 @@ impl Config {
     pub fn spec(&self) -> &Spec {
         &self.spec
     }
+
+    /// Return a copy with the private keys, the passwords in the URLs, the bearer tokens and the
+    /// header values redacted, for logging and reporting.
+    pub fn redacted(&self) -> Self {
+        let mut config = self.clone();
+        config.spec.bencher_private_key = REDACTED.to_owned();
+        config.spec.miner.private_key = REDACTED.to_owned();
+        for url in config.rpc_urls.iter_mut() {
+            *url = url.redacted();
+        }
+        for competing_miner in config.spec.competing_miners.iter_mut() {
+            competing_miner.rpc_url = competing_miner.rpc_url.redacted();
+            competing_miner.miner.private_key = REDACTED.to_owned();
+        }
+        for auth in config.spec.rpc_auth.iter_mut() {
+            auth.url = auth.url.redacted();
+            if auth.bearer_token.is_some() {
+                auth.bearer_token = Some(REDACTED.to_owned());
+            }
+            for value in auth.headers.values_mut() {
+                *value = REDACTED.to_owned();
+            }
+        }
+        config
+    }
 }
 
 impl Spec {
@@ -86,6 +129,8 @@ impl Spec {
         *CELLBASE_MATURITY.lock().unwrap() = spec.consensus_cellbase_maturity;
         *CONFIRMATION_BLOCKS.lock().unwrap() = spec.confirmation_blocks;
         *METHOD_TO_EVAL_NET_STABLE.lock().unwrap() = spec.method_to_eval_network_stable;
+        *RPC_RETRY_POLICY.lock().unwrap() = spec.rpc_retry;
+        *RPC_AUTH.lock().unwrap() = spec.rpc_auth.clone();
 
         Ok(spec)
     }
@@ -97,6 +142,10 @@ impl Spec {
     pub fn metrics_path(&self) -> PathBuf {
         PathBuf::from(&self.data_dir).join("metrics.json")
     }
+
+    pub fn txpool_series_path(&self) -> PathBuf {
+        PathBuf::from(&self.data_dir).join("txpool.jsonl")
+    }
 }
 
 impl TransactionType {
@@ -121,4 +170,12 @@ impl Url {
         let url = url::Url::parse(input)?;
         Ok(Url(url))
     }
+
+    pub fn redacted(&self) -> Self {
+        let mut url = self.0.clone();
+        if url.password().is_some() {
+            let _ = url.set_password(Some(REDACTED));
+        }
+        Url(url)
+    }
 }
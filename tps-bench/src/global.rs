@@ -1,5 +1,8 @@
 use crate::genesis_info::GenesisInfo;
+use crate::miner::BlockAssemblySample;
 use crate::net_monitor::MethodToEvalNetStable;
+use crate::propagation::BlockPropagation;
+use crate::rpc::{EndpointAuth, RetryPolicy};
 
 use ckb_types::core::DepType;
 use ckb_types::packed::{Byte32, CellDep, OutPoint};
@@ -8,6 +11,8 @@ use ckb_types::{h256, H256};
 use lazy_static::lazy_static;
 use std::fs::File;
 use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::runtime::{Builder, Handle};
 
 pub const MIN_SECP_CELL_CAPACITY: u64 = 61_0000_0000;
 pub const DEP_GROUP_TRANSACTION_INDEX: usize = 1;
@@ -38,4 +43,30 @@ lazy_static! {
     pub static ref METHOD_TO_EVAL_NET_STABLE: Mutex<MethodToEvalNetStable> =
         Mutex::new(MethodToEvalNetStable::default());
     pub static ref METRICS_RECORDER: Mutex<Option<File>> = Mutex::new(None);
+    pub static ref BENCH_PHASE: Mutex<String> = Mutex::new("prepare".to_owned());
+    // Tells the lines of this run apart in the append-only data files, the start time in unix
+    // milliseconds
+    pub static ref RUN_ID: String = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time before unix epoch")
+        .as_millis()
+        .to_string();
+    // `Some` only while a benchmark is running, so that the other miners do not accumulate samples
+    pub static ref BLOCK_ASSEMBLY_SAMPLES: Mutex<Option<Vec<BlockAssemblySample>>> = Mutex::new(None);
+    pub static ref BLOCK_PROPAGATION: Mutex<BlockPropagation> =
+        Mutex::new(BlockPropagation::default());
+    pub static ref RPC_RETRY_POLICY: Mutex<RetryPolicy> = Mutex::new(RetryPolicy::default());
+    pub static ref RPC_AUTH: Mutex<Vec<EndpointAuth>> = Mutex::new(Vec::new());
+    // The runtime executing the RPC requests of all the endpoints, alive for the whole process
+    pub static ref RPC_RUNTIME: Handle = {
+        let runtime = Builder::new()
+            .threaded_scheduler()
+            .enable_all()
+            .thread_name("rpc")
+            .build()
+            .expect("build rpc runtime");
+        let handle = runtime.handle().clone();
+        std::mem::forget(runtime);
+        handle
+    };
 }
@@ -0,0 +1,342 @@
+#![allow(clippy::mutable_key_type)]
+use crate::net::Net;
+use crate::statistics::Percentiles;
+use crate::util::call_until_ok;
+
+use ckb_jsonrpc_types::Status;
+use ckb_types::core::{BlockNumber, BlockView};
+use ckb_types::packed::{Byte32, ProposalShortId};
+use crossbeam_channel::{bounded, Sender, TryRecvError};
+use log::warn;
+use metrics::gauge;
+use rand::Rng;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread::{sleep, spawn};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// A transaction not committed within this after being sent is counted as lost, and no longer
+// tracked or polled
+pub const TRANSACTION_COMMITMENT_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Tracks the end-to-end latency of every transaction sent in a benchmark, from
+/// `send_transaction` to the timestamp of the block which commits it.
+///
+/// Both ends are wall-clock timestamps, the one of the bench client and the one of the node
+/// assembling the block, so the clocks are expected to be synchronized.
+///
+/// A random subset of the transactions, by `lifecycle_sample_rate`, is also tracked stage by
+/// stage: pending, proposed and committed.
+#[derive(Clone, Default)]
+pub struct LatencyTracker {
+    inner: Arc<Mutex<LatencyTrackerInner>>,
+}
+
+#[derive(Default)]
+struct LatencyTrackerInner {
+    sent_at: HashMap<Byte32, u64>,
+    sent_count: u64,
+    lost_count: u64,
+    confirmation_latencies: Vec<u64>,
+
+    lifecycle_sample_rate: f64,
+    lifecycles: HashMap<Byte32, Lifecycle>,
+    proposal_ids: HashMap<ProposalShortId, Byte32>,
+}
+
+// The timestamps of the stages of a sampled transaction. `pending_at`, `proposed_at` and
+// `committed_at` are observed via `get_transaction` by the bench client, while `proposal` and
+// `commitment` are the number and timestamp of the blocks proposing and committing it.
+#[derive(Default, Clone)]
+struct Lifecycle {
+    endpoint: usize,
+    sent_at: u64,
+    pending_at: Option<u64>,
+    proposed_at: Option<u64>,
+    committed_at: Option<u64>,
+    proposal: Option<(BlockNumber, u64)>,
+    commitment: Option<(BlockNumber, u64)>,
+    lost: bool,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct LatencyMetrics {
+    sent_transactions: u64,
+    committed_transactions: u64,
+    /// Not committed within `TRANSACTION_COMMITMENT_TIMEOUT`
+    lost_transactions: u64,
+    confirmation_latency_ms: Percentiles,
+    lifecycle: LifecycleMetrics,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct LifecycleMetrics {
+    sampled_transactions: u64,
+    lost_transactions: u64,
+    /// From `send_transaction` to be observed pending
+    sent_to_pending_ms: Percentiles,
+    /// From be observed pending to be observed proposed
+    pending_to_proposed_ms: Percentiles,
+    /// From be observed proposed to be observed committed
+    proposed_to_committed_ms: Percentiles,
+    /// From the proposal block to the commitment block, in block timestamps
+    proposal_to_commitment_ms: Percentiles,
+    /// From the proposal block to the commitment block, in block numbers
+    proposal_to_commitment_blocks: Percentiles,
+}
+
+impl LatencyTracker {
+    pub fn new(lifecycle_sample_rate: f64) -> Self {
+        let tracker = Self::default();
+        tracker.inner.lock().unwrap().lifecycle_sample_rate =
+            lifecycle_sample_rate.max(0.0).min(1.0);
+        tracker
+    }
+
+    pub fn record_sent(&self, tx_hash: Byte32, endpoint: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        let sent_at = unix_time_ms();
+        inner.sent_at.insert(tx_hash.clone(), sent_at);
+        inner.sent_count += 1;
+
+        let lifecycle_sample_rate = inner.lifecycle_sample_rate;
+        if lifecycle_sample_rate > 0.0 && rand::thread_rng().gen_bool(lifecycle_sample_rate) {
+            let lifecycle = Lifecycle {
+                endpoint,
+                sent_at,
+                ..Default::default()
+            };
+            inner
+                .proposal_ids
+                .insert(ProposalShortId::from_tx_hash(&tx_hash), tx_hash.clone());
+            inner.lifecycles.insert(tx_hash, lifecycle);
+        }
+    }
+
+    /// Forget a transaction which failed to be sent.
+    pub fn forget(&self, tx_hash: &Byte32) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.sent_at.remove(tx_hash).is_some() {
+            inner.sent_count -= 1;
+        }
+        if inner.lifecycles.remove(tx_hash).is_some() {
+            inner
+                .proposal_ids
+                .remove(&ProposalShortId::from_tx_hash(tx_hash));
+        }
+    }
+
+    pub fn record_block(&self, block: &BlockView) {
+        let mut inner = self.inner.lock().unwrap();
+        for tx_hash in block.tx_hashes().iter().skip(1) {
+            if let Some(sent_at) = inner.sent_at.remove(tx_hash) {
+                let latency = block.timestamp().saturating_sub(sent_at);
+                inner.confirmation_latencies.push(latency);
+            }
+            if let Some(lifecycle) = inner
+                .lifecycles
+                .get_mut(tx_hash)
+                .filter(|lifecycle| !lifecycle.lost)
+            {
+                lifecycle.commitment = Some((block.number(), block.timestamp()));
+            }
+        }
+
+        // A transaction may be proposed more than once, take the first proposal
+        for proposal_id in block.union_proposal_ids() {
+            if let Some(tx_hash) = inner.proposal_ids.remove(&proposal_id) {
+                if let Some(lifecycle) = inner.lifecycles.get_mut(&tx_hash) {
+                    lifecycle.proposal = Some((block.number(), block.timestamp()));
+                }
+            }
+        }
+    }
+
+    pub fn record_status(&self, tx_hash: &Byte32, status: Status) {
+        let now = unix_time_ms();
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(lifecycle) = inner.lifecycles.get_mut(tx_hash) {
+            let observed_at = match status {
+                Status::Pending => &mut lifecycle.pending_at,
+                Status::Proposed => &mut lifecycle.proposed_at,
+                Status::Committed => &mut lifecycle.committed_at,
+            };
+            if observed_at.is_none() {
+                *observed_at = Some(now);
+            }
+        }
+    }
+
+    // Count the transactions not committed within `TRANSACTION_COMMITMENT_TIMEOUT` as lost, and
+    // stop tracking them.
+    fn expire(&self) {
+        let deadline =
+            unix_time_ms().saturating_sub(TRANSACTION_COMMITMENT_TIMEOUT.as_millis() as u64);
+        let mut inner = self.inner.lock().unwrap();
+        let before = inner.sent_at.len();
+        inner.sent_at.retain(|_, sent_at| *sent_at > deadline);
+        inner.lost_count += (before - inner.sent_at.len()) as u64;
+
+        let LatencyTrackerInner {
+            lifecycles,
+            proposal_ids,
+            ..
+        } = &mut *inner;
+        for (tx_hash, lifecycle) in lifecycles.iter_mut() {
+            if !lifecycle.lost && lifecycle.commitment.is_none() && lifecycle.sent_at <= deadline {
+                lifecycle.lost = true;
+                proposal_ids.remove(&ProposalShortId::from_tx_hash(tx_hash));
+            }
+        }
+    }
+
+    // Return the sampled transactions whose committed status is not observed yet, and the
+    // endpoints they were sent to.
+    fn observing_transactions(&self) -> Vec<(Byte32, usize)> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .lifecycles
+            .iter()
+            .filter(|(_, lifecycle)| lifecycle.committed_at.is_none() && !lifecycle.lost)
+            .map(|(tx_hash, lifecycle)| (tx_hash.clone(), lifecycle.endpoint))
+            .collect()
+    }
+
+    pub fn metrics(&self) -> LatencyMetrics {
+        let inner = self.inner.lock().unwrap();
+        LatencyMetrics {
+            sent_transactions: inner.sent_count,
+            committed_transactions: inner.confirmation_latencies.len() as u64,
+            lost_transactions: inner.lost_count,
+            confirmation_latency_ms: Percentiles::new(&inner.confirmation_latencies),
+            lifecycle: LifecycleMetrics::eval(inner.lifecycles.values()),
+        }
+    }
+}
+
+impl LifecycleMetrics {
+    fn eval<'a>(lifecycles: impl Iterator<Item = &'a Lifecycle> + Clone) -> Self {
+        let durations = |stage: fn(&Lifecycle) -> Option<u64>| {
+            lifecycles.clone().filter_map(stage).collect::<Vec<_>>()
+        };
+        let sent_to_pending_ms = durations(|l| {
+            l.pending_at
+                .map(|pending| pending.saturating_sub(l.sent_at))
+        });
+        let pending_to_proposed_ms = durations(|l| {
+            l.pending_at.and_then(|pending| {
+                l.proposed_at
+                    .map(|proposed| proposed.saturating_sub(pending))
+            })
+        });
+        let proposed_to_committed_ms = durations(|l| {
+            l.proposed_at.and_then(|proposed| {
+                l.committed_at
+                    .map(|committed| committed.saturating_sub(proposed))
+            })
+        });
+        let proposal_to_commitment_ms = durations(|l| {
+            l.proposal.and_then(|(_, proposal)| {
+                l.commitment
+                    .map(|(_, commitment)| commitment.saturating_sub(proposal))
+            })
+        });
+        let proposal_to_commitment_blocks = durations(|l| {
+            l.proposal.and_then(|(proposal, _)| {
+                l.commitment
+                    .map(|(commitment, _)| commitment.saturating_sub(proposal))
+            })
+        });
+        Self {
+            sampled_transactions: lifecycles.clone().count() as u64,
+            lost_transactions: lifecycles.filter(|l| l.lost).count() as u64,
+            sent_to_pending_ms: Percentiles::new(&sent_to_pending_ms),
+            pending_to_proposed_ms: Percentiles::new(&pending_to_proposed_ms),
+            proposed_to_committed_ms: Percentiles::new(&proposed_to_committed_ms),
+            proposal_to_commitment_ms: Percentiles::new(&proposal_to_commitment_ms),
+            proposal_to_commitment_blocks: Percentiles::new(&proposal_to_commitment_blocks),
+        }
+    }
+}
+
+/// Follow the confirmed blocks and record the proposed and committed transactions into
+/// `tracker`, until the returned sender is dropped.
+pub fn spawn_commit_watcher(net: Net, tracker: LatencyTracker) -> Sender<()> {
+    let (stopper, stop_receiver) = bounded::<()>(0);
+    spawn(move || {
+        let mut number = call_until_ok("net.get_confirmed_tip_number", || {
+            net.get_confirmed_tip_number()
+        })
+        .unwrap_or_else(|err| panic!("net.get_confirmed_tip_number error: {}", err));
+        loop {
+            if let Err(TryRecvError::Disconnected) = stop_receiver.try_recv() {
+                return;
+            }
+            tracker.expire();
+
+            let confirmed_tip_number = match net.get_confirmed_tip_number() {
+                Ok(confirmed_tip_number) => confirmed_tip_number,
+                Err(err) => {
+                    warn!("latency get_confirmed_tip_number error: {}", err);
+                    sleep(Duration::from_millis(500));
+                    continue;
+                }
+            };
+            gauge!(
+                "tps_bench.chain.confirmed_tip_number",
+                confirmed_tip_number as i64
+            );
+            if confirmed_tip_number <= number {
+                sleep(Duration::from_millis(500));
+                continue;
+            }
+            // Resume from the last recorded block on errors, so no block is missed
+            for block in net.get_blocks_in_range(number + 1, confirmed_tip_number) {
+                match block {
+                    Ok(block) => {
+                        tracker.record_block(&block);
+                        number = block.number();
+                    }
+                    Err(err) => {
+                        warn!("latency get_blocks_in_range error: {}", err);
+                        sleep(Duration::from_millis(500));
+                        break;
+                    }
+                }
+            }
+        }
+    });
+    stopper
+}
+
+/// Poll `get_transaction` of the sampled transactions on the endpoints they were sent to, and
+/// record the first time each status is observed into `tracker`, until the returned sender is
+/// dropped.
+pub fn spawn_lifecycle_sampler(net: Net, tracker: LatencyTracker) -> Sender<()> {
+    let (stopper, stop_receiver) = bounded::<()>(0);
+    spawn(move || loop {
+        if let Err(TryRecvError::Disconnected) = stop_receiver.try_recv() {
+            return;
+        }
+
+        for (tx_hash, endpoint) in tracker.observing_transactions() {
+            match net.endpoints()[endpoint].get_transaction(tx_hash.clone()) {
+                Ok(Some(transaction)) => {
+                    tracker.record_status(&tx_hash, transaction.tx_status.status);
+                }
+                Ok(None) => {}
+                Err(err) => warn!("latency get_transaction({:#x}) error: {}", tx_hash, err),
+            }
+        }
+        sleep(Duration::from_millis(200));
+    });
+    stopper
+}
+
+pub fn unix_time_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time before unix epoch")
+        .as_millis() as u64
+}
@@ -2,7 +2,8 @@
 extern crate clap;
 
 use ckb_types::core::BlockView;
-use log::{info, LevelFilter};
+use ckb_types::prelude::*;
+use log::{error, info, LevelFilter};
 use metrics_exporter_http::HttpExporter;
 use metrics_observer_prometheus::PrometheusBuilder;
 use metrics_runtime::Receiver;
@@ -10,38 +11,67 @@ use serde_json::json;
 use simplelog::{CombinedLogger, WriteLogger};
 use std::fs::OpenOptions;
 use std::net::SocketAddr;
+use std::thread::spawn;
 
 use crate::account::Account;
-use crate::benchmark::BenchmarkConfig;
-use crate::command::{commandline, CommandLine};
+use crate::balance::{print_balance_rows, Balance};
+use crate::benchmark::{
+    default_lifecycle_sample_rate, default_max_coefficient_of_variation,
+    default_propagation_sample_rate, default_repetitions, default_sub_windows, BenchmarkConfig,
+};
+use crate::coin_selection::CoinSelection;
+use crate::command::{commandline, CommandLine, OutputFormat};
+use crate::compare::{compare, print_comparisons};
 use crate::config::{Config, TransactionType};
-use crate::global::{GENESIS_INFO, METRICS_RECORDER};
+use crate::global::{GENESIS_INFO, METRICS_RECORDER, RUN_ID};
+use crate::metric::report_metrics;
 use crate::miner::Miner;
 use crate::net::Net;
-use crate::net_monitor::Metrics;
-use crate::rpc::Jsonrpc;
-use crate::threads::{spawn_miner, spawn_pull_utxos, spawn_transfer_utxos};
+use crate::report::render_report;
+use crate::rpc::{Jsonrpc, RpcError};
+use crate::sweep::sweep;
+use crate::threads::{spawn_competing_miners, spawn_miner, spawn_pull_utxos, spawn_transfer_utxos};
+use crate::txpool_series::spawn_txpool_recorder;
 
+// Declared first so that `prompt_and_exit!` is available to every module
+pub mod util;
+pub mod balance;
 pub mod benchmark;
+pub mod block_interval;
+pub mod client_monitor;
+pub mod coin_selection;
 pub mod global;
+pub mod latency;
 pub mod miner;
 pub mod net;
 pub mod net_monitor;
+pub mod periodicity;
+pub mod propagation;
+pub mod send_error;
+pub mod statistics;
 pub mod threads;
 pub mod transfer;
-pub mod util;
+pub mod txpool_series;
 pub mod account;
 pub mod command;
+pub mod compare;
 pub mod config;
 pub mod genesis_info;
+pub mod metric;
+pub mod report;
 pub mod rpc;
+pub mod sweep;
 pub mod utxo;
 
 fn main() {
     match commandline() {
         CommandLine::MineMode(config, blocks) => {
-            info!("\nTPSBench start with configuration: {}", json!(config));
             init_logger(&config);
+            info!(
+                "\nTPSBench start with configuration: {}",
+                json!(config.redacted())
+            );
+            info!("[RUN ID] {}", *RUN_ID);
             init_global_genesis_info(&config);
 
             let miner_config = &config.miner;
@@ -50,14 +80,33 @@ fn main() {
             miner.generate_blocks(blocks);
         }
         CommandLine::BenchMode(config, skip_best_tps_caculation) => {
-            info!("\nTPSBench start with configuration: {}", json!(config));
             init_logger(&config);
+            info!(
+                "\nTPSBench start with configuration: {}",
+                json!(config.redacted())
+            );
+            info!("[RUN ID] {}", *RUN_ID);
             init_metrics_recorder(&config);
             init_metrics(&config);
             init_global_genesis_info(&config);
+            spawn_txpool_recorder(&config);
 
             let rpc_urls = config.rpc_urls();
             let net = Net::connect_all(config.rpc_urls());
+            let node_versions = net
+                .endpoints()
+                .iter()
+                .map(|rpc| {
+                    let local_node = rpc.local_node_info().unwrap_or_else(|err| {
+                        prompt_and_exit!("local_node_info({}) error: {}", rpc.uri(), err);
+                    });
+                    json!({
+                        "endpoint": rpc.uri(),
+                        "version": local_node.version,
+                    })
+                })
+                .collect::<Vec<_>>();
+            info!("[NODE VERSIONS] {}", json!(node_versions));
 
             // Bencher
             let bencher = Account::new(&config.bencher_private_key);
@@ -65,15 +114,27 @@ fn main() {
             // Miner
             let miner_config = &config.miner;
             let miner = Miner::new(&miner_config, rpc_urls);
-            spawn_miner(&miner);
+            if config.competing_miners.is_empty() {
+                spawn_miner(&miner);
+            } else {
+                // `miner` still mines on demand while pulling the utxos below
+                miner.assert_block_assembler();
+                spawn_competing_miners(&config);
+            }
 
             // Transfer all miner's utxo to bencher
             if miner.lock_script() != bencher.lock_script() {
-                let (_, miner_utxo_r) = spawn_pull_utxos(&config, &miner, &miner);
+                let (_, miner_utxo_r) =
+                    spawn_pull_utxos(&config, &miner, &miner).unwrap_or_else(|err| {
+                        prompt_and_exit!("spawn_pull_utxos(miner) error: {}", err);
+                    });
                 spawn_transfer_utxos(&config, &miner, &bencher, miner_utxo_r);
             }
 
-            let (_, bencher_utxo_r) = spawn_pull_utxos(&config, &bencher, &miner);
+            let (_, bencher_utxo_r) =
+                spawn_pull_utxos(&config, &bencher, &miner).unwrap_or_else(|err| {
+                    prompt_and_exit!("spawn_pull_utxos(bencher) error: {}", err);
+                });
 
             // Benchmark
             for benchmark in config.benchmarks.iter() {
@@ -91,21 +152,132 @@ fn main() {
                     transaction_type: TransactionType::In2Out2,
                     send_delay: 0,
                     method_to_eval_net_stable: None,
+                    coin_selection: CoinSelection::default(),
+                    lifecycle_sample_rate: default_lifecycle_sample_rate(),
+                    propagation_sample_rate: default_propagation_sample_rate(),
+                    repetitions: default_repetitions(),
+                    sub_windows: default_sub_windows(),
+                    max_coefficient_of_variation: default_max_coefficient_of_variation(),
                 };
                 let best_tps = benchmark.find_best_bench(&net, &bencher, &bencher, &bencher_utxo_r);
                 info!("Best TPS: {}", best_tps);
                 println!("TPS: {}", best_tps);
             }
         }
-        CommandLine::MetricMode(rpc_urls) => {
+        CommandLine::MetricMode(rpc_urls, options) => {
             info!("\n Caculate TPS");
 
             let rpc_urls = rpc_urls.iter().map(|url| url.as_str()).collect();
             let net = Net::connect_all(rpc_urls);
+            report_metrics(&net, &options);
+        }
+        CommandLine::AccountMode(config, private_keys, lock_hashes, format) => {
+            init_logger(&config);
+            init_global_genesis_info(&config);
+
+            let net = Net::connect_all(config.rpc_urls());
+            let tip_header = net.get_confirmed_tip_header().unwrap_or_else(|err| {
+                prompt_and_exit!("get_confirmed_tip_header error: {}", err);
+            });
+            let balance_or_exit = |balance: Result<Balance, RpcError>| {
+                balance.unwrap_or_else(|err| {
+                    prompt_and_exit!("account error: {}", err);
+                })
+            };
+            let mut balances = Vec::new();
+            if private_keys.is_empty() && lock_hashes.is_empty() {
+                let bencher = Account::new(&config.bencher_private_key);
+                let miner = Miner::new(&config.miner, config.rpc_urls());
+                balances.push(balance_or_exit(Balance::of_account(
+                    &net,
+                    "bencher",
+                    &bencher,
+                    &tip_header,
+                )));
+                if miner.lock_script() != bencher.lock_script() {
+                    balances.push(balance_or_exit(Balance::of_account(
+                        &net,
+                        "miner",
+                        &miner,
+                        &tip_header,
+                    )));
+                }
+            }
+            for private_key in private_keys.iter() {
+                let account = Account::new(private_key);
+                balances.push(balance_or_exit(Balance::of_account(
+                    &net,
+                    &format!("{:#x}", account.lock_hash()),
+                    &account,
+                    &tip_header,
+                )));
+            }
+            for lock_hash in lock_hashes.iter() {
+                balances.push(balance_or_exit(Balance::of_lock_hash(
+                    &net,
+                    &lock_hash.pack(),
+                    &tip_header,
+                )));
+            }
+
+            match format {
+                OutputFormat::Table => balances.iter().for_each(Balance::print_table),
+                OutputFormat::Json => println!("{}", json!(balances)),
+                OutputFormat::Csv => print_balance_rows(format, &balances),
+            }
+        }
+        CommandLine::SweepMode(config, account, cells, max_transaction_bytes) => {
+            init_logger(&config);
+            info!(
+                "\nTPSBench start with configuration: {}",
+                json!(config.redacted())
+            );
+            info!("[RUN ID] {}", *RUN_ID);
+            init_global_genesis_info(&config);
 
-            let tip_block_number = net.get_confirmed_tip_number();
-            let result = Metrics::eval_blocks(&net, 1, tip_block_number);
-            println!("{:?}", result);
+            let net = Net::connect_all(config.rpc_urls());
+            let miner = Miner::new(&config.miner, config.rpc_urls());
+            miner.assert_block_assembler();
+            let account = match account.as_str() {
+                "miner" => (*miner).clone(),
+                _ => Account::new(&config.bencher_private_key),
+            };
+            sweep(&net, &miner, &account, cells, max_transaction_bytes).unwrap_or_else(|err| {
+                prompt_and_exit!("sweep error: {}", err);
+            });
+        }
+        CommandLine::CompareMode(baseline, candidate, tolerance, format) => {
+            let (comparisons, missing) = compare(&baseline, &candidate, tolerance);
+            print_comparisons(format, &comparisons);
+            // A changed parameter or a failed run must not pass the gate silently
+            if !missing.is_empty() {
+                prompt_and_exit!(
+                    "{} baseline benchmarks missing from the candidate: {}",
+                    missing.len(),
+                    missing.join(", ")
+                );
+            }
+            if comparisons.is_empty() {
+                prompt_and_exit!("no benchmark matches between the baseline and the candidate");
+            }
+            let regressions = comparisons
+                .iter()
+                .filter(|comparison| comparison.is_regression())
+                .count();
+            if regressions > 0 {
+                prompt_and_exit!("{} regressions exceed the tolerance", regressions);
+            }
+        }
+        CommandLine::ReportMode(data_dir, format, output) => {
+            let report = render_report(&data_dir, format);
+            match output {
+                Some(output) => {
+                    std::fs::write(&output, report).unwrap_or_else(|err| {
+                        prompt_and_exit!("write {} error: {}", output.to_string_lossy(), err);
+                    });
+                }
+                None => print!("{}", report),
+            }
         }
     }
 }
@@ -140,43 +312,54 @@ fn init_metrics_recorder(config: &Config) {
     );
 }
 
-// TODO It's just draft version, I don't really know how to init metrics service
+/// Serve the metrics in Prometheus format on `metrics_url` for the life of the process
 fn init_metrics(config: &Config) {
-    if config.metrics_url.is_none() {
-        info!("No start metrics service");
-        return;
-    }
+    let metrics_url = match config.metrics_url.as_ref() {
+        Some(metrics_url) => metrics_url,
+        None => {
+            info!("No start metrics service");
+            return;
+        }
+    };
 
-    let metrics_url = config.metrics_url.as_ref().unwrap();
-    let listen = metrics_url.parse::<SocketAddr>().unwrap();
-    let receiver = Receiver::builder().build().unwrap();
+    let listen = metrics_url
+        .parse::<SocketAddr>()
+        .unwrap_or_else(|err| panic!("parse metrics_url {} error: {}", metrics_url, err));
+    let receiver = Receiver::builder().build().expect("build metrics receiver");
     let controller = receiver.controller();
-    let builder = PrometheusBuilder::new();
-    let exporter = HttpExporter::new(controller, builder, listen);
-
-    let runtime = tokio::runtime::Builder::default()
-        .threaded_scheduler()
-        .enable_all()
-        .build()
-        .unwrap();
-    runtime.handle().spawn(async {
-        tokio::spawn(exporter.async_run());
-    });
+    receiver.install();
+    let exporter = HttpExporter::new(controller, PrometheusBuilder::new(), listen);
 
-    // info!("Metrics URL: {}", metrics_url);
+    spawn(move || {
+        let mut runtime = tokio::runtime::Builder::default()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .expect("build metrics runtime");
+        if let Err(err) = runtime.block_on(exporter.async_run()) {
+            error!("metrics exporter on {} error: {}", listen, err);
+        }
+    });
+    info!("Metrics URL: http://{}", listen);
 }
 
 /// Initialize the global `GENESIS_INFO` with the genesis block
 pub fn init_global_genesis_info(config: &Config) {
     info!("[START] init_global_genesis_info");
-    let url = config.rpc_urls()[0];
-    let rpc = Jsonrpc::connect(url);
+    let rpc = Jsonrpc::connect(config.rpc_urls()[0]);
     let genesis_block: BlockView = rpc
         .get_block_by_number(0)
+        .unwrap_or_else(|err| {
+            prompt_and_exit!(
+                "Jsonrpc::get_block_by_number({}, 0), error: {}",
+                rpc.uri(),
+                err
+            );
+        })
         .unwrap_or_else(|| {
             panic!(
                 "Jsonrpc::get_block_by_number({}, 0), error: return None",
-                url
+                rpc.uri()
             )
         })
         .into();
@@ -0,0 +1,355 @@
+use crate::command::OutputFormat;
+use crate::net::Net;
+use crate::net_monitor::{BlockUsage, Metrics};
+use crate::periodicity::Periodicity;
+use crate::rpc::RpcError;
+
+use ckb_types::core::{BlockNumber, BlockView, HeaderView};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::str::FromStr;
+
+/// A bound of the evaluated blocks range, either a block number, or a unix timestamp in
+/// milliseconds written as `time:<timestamp>`
+#[derive(Debug, Clone, Copy)]
+pub enum BlockBound {
+    Number(BlockNumber),
+    Timestamp(u64),
+}
+
+impl FromStr for BlockBound {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |number: &str| {
+            number
+                .parse::<u64>()
+                .map_err(|err| format!("invalid block bound {}: {}", s, err))
+        };
+        match s.strip_prefix("time:") {
+            Some(timestamp) => parse(timestamp).map(BlockBound::Timestamp),
+            None => parse(s).map(BlockBound::Number),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MetricOptions {
+    pub from: Option<BlockBound>,
+    pub to: Option<BlockBound>,
+    pub per_block: bool,
+    /// The window size, in blocks, of the sliding-window TPS series
+    pub window: Option<u64>,
+    /// Analyze the periodicity with the proposal window
+    pub periodicity: Option<(BlockNumber, BlockNumber)>,
+    pub format: OutputFormat,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct BlockRow {
+    number: BlockNumber,
+    timestamp: u64,
+    interval_ms: u64,
+    #[serde(flatten)]
+    usage: BlockUsage,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct WindowTps {
+    start_block_number: BlockNumber,
+    end_block_number: BlockNumber,
+    tps: u64,
+}
+
+/// Evaluate the metrics of the blocks in the range of `options` and print them.
+pub fn report_metrics(net: &Net, options: &MetricOptions) {
+    let tip_number = exit_on_error(net.get_confirmed_tip_number());
+    let from_number = options
+        .from
+        .map(|bound| exit_on_error(resolve_from(net, bound, tip_number)))
+        .unwrap_or(1);
+    let end_number = options
+        .to
+        .map(|bound| exit_on_error(resolve_to(net, bound, tip_number)))
+        .unwrap_or(tip_number);
+    if from_number >= end_number || end_number > tip_number {
+        prompt_and_exit!(
+            "invalid blocks range [{}, {}], confirmed tip number: {}",
+            from_number,
+            end_number,
+            tip_number
+        );
+    }
+
+    // Fetch the range once for all the views below
+    let fetched_blocks = exit_on_error(
+        net.get_blocks_in_range(from_number, end_number)
+            .collect::<Result<Vec<_>, _>>(),
+    );
+    let metrics = exit_on_error(Metrics::eval_fetched_blocks(
+        net,
+        from_number,
+        end_number,
+        &fetched_blocks,
+    ));
+    let periodicity = options
+        .periodicity
+        .map(|window| Periodicity::eval(&fetched_blocks, from_number, end_number, window));
+    let blocks = if options.per_block || options.window.is_some() {
+        exit_on_error(eval_block_rows(net, from_number, &fetched_blocks))
+    } else {
+        Vec::new()
+    };
+    let window_tps = options
+        .window
+        .map(|window| eval_window_tps(&blocks, window as usize));
+
+    match options.format {
+        OutputFormat::Json => {
+            let mut result = Map::new();
+            result.insert("metrics".to_owned(), json!(metrics));
+            if let Some(periodicity) = periodicity {
+                result.insert("periodicity".to_owned(), json!(periodicity));
+            }
+            if options.per_block {
+                result.insert("blocks".to_owned(), json!(blocks));
+            }
+            if let Some(window_tps) = window_tps {
+                result.insert("window_tps".to_owned(), json!(window_tps));
+            }
+            println!("{}", Value::Object(result));
+        }
+        format => {
+            print_key_values(format, "metrics", &json!(metrics));
+            if let Some(periodicity) = periodicity {
+                print_key_values(format, "periodicity", &json!(periodicity));
+            }
+            if options.per_block {
+                print_records(format, "blocks", &json!(blocks));
+            }
+            if let Some(window_tps) = window_tps {
+                print_records(format, "window_tps", &json!(window_tps));
+            }
+        }
+    }
+}
+
+fn exit_on_error<T>(result: Result<T, RpcError>) -> T {
+    result.unwrap_or_else(|err| {
+        prompt_and_exit!("metric error: {}", err);
+    })
+}
+
+fn resolve_from(
+    net: &Net,
+    bound: BlockBound,
+    tip_number: BlockNumber,
+) -> Result<BlockNumber, RpcError> {
+    match bound {
+        BlockBound::Number(number) => Ok(number),
+        BlockBound::Timestamp(timestamp) => {
+            first_block_since(net, tip_number, |header| header.timestamp() >= timestamp)
+        }
+    }
+}
+
+fn resolve_to(
+    net: &Net,
+    bound: BlockBound,
+    tip_number: BlockNumber,
+) -> Result<BlockNumber, RpcError> {
+    match bound {
+        BlockBound::Number(number) => Ok(number),
+        BlockBound::Timestamp(timestamp) => {
+            first_block_since(net, tip_number, |header| header.timestamp() > timestamp)
+                .map(|number| number.saturating_sub(1))
+        }
+    }
+}
+
+// Binary search the first block in `[0, tip_number]` satisfying `predicate`, which should be
+// monotonic along the chain. Return `tip_number + 1` if none satisfies it.
+fn first_block_since<F>(
+    net: &Net,
+    tip_number: BlockNumber,
+    predicate: F,
+) -> Result<BlockNumber, RpcError>
+where
+    F: Fn(&HeaderView) -> bool,
+{
+    let (mut low, mut high) = (0, tip_number + 1);
+    while low < high {
+        let middle = low + (high - low) / 2;
+        let header: HeaderView = net
+            .get_header_by_number(middle)?
+            .ok_or_else(|| RpcError::NotFound(format!("get_header_by_number({})", middle)))?
+            .into();
+        if predicate(&header) {
+            high = middle;
+        } else {
+            low = middle + 1;
+        }
+    }
+    Ok(low)
+}
+
+fn eval_block_rows(
+    net: &Net,
+    from_number: BlockNumber,
+    blocks: &[BlockView],
+) -> Result<Vec<BlockRow>, RpcError> {
+    let mut parent_timestamp = None;
+    if from_number > 0 {
+        let parent: HeaderView = net
+            .get_header_by_number(from_number - 1)?
+            .ok_or_else(|| {
+                RpcError::NotFound(format!("get_header_by_number({})", from_number - 1))
+            })?
+            .into();
+        parent_timestamp = Some(parent.timestamp());
+    }
+
+    Ok(blocks
+        .iter()
+        .map(|block| {
+            let interval_ms = parent_timestamp
+                .map(|parent| block.timestamp().saturating_sub(parent))
+                .unwrap_or(0);
+            parent_timestamp = Some(block.timestamp());
+            BlockRow {
+                number: block.number(),
+                timestamp: block.timestamp(),
+                interval_ms,
+                usage: BlockUsage::new(block),
+            }
+        })
+        .collect())
+}
+
+// The TPS of every `window` consecutive blocks, counted the same as `Metrics::eval_blocks`
+fn eval_window_tps(blocks: &[BlockRow], window: usize) -> Vec<WindowTps> {
+    if window == 0 || window >= blocks.len() {
+        return Vec::new();
+    }
+
+    (window..blocks.len())
+        .filter_map(|end| {
+            let start = end - window;
+            let elapsed_ms = blocks[end]
+                .timestamp
+                .saturating_sub(blocks[start].timestamp);
+            if elapsed_ms == 0 {
+                return None;
+            }
+            let txns = blocks[start + 1..=end]
+                .iter()
+                .map(|block| block.usage.transactions)
+                .sum::<u64>();
+            Some(WindowTps {
+                start_block_number: blocks[start].number,
+                end_block_number: blocks[end].number,
+                tps: (txns as f64 * 1000.0 / elapsed_ms as f64) as u64,
+            })
+        })
+        .collect()
+}
+
+// Print the flattened fields of `value` as `key, value` rows
+fn print_key_values(format: OutputFormat, title: &str, value: &Value) {
+    let mut rows = Vec::new();
+    flatten("", value, &mut rows);
+    let rows = rows
+        .into_iter()
+        .map(|(key, value)| vec![key, value])
+        .collect::<Vec<_>>();
+    print_rows(
+        format,
+        title,
+        &["key".to_owned(), "value".to_owned()],
+        &rows,
+    );
+}
+
+// Print the array of objects `records` with one row per record and one column per field
+fn print_records(format: OutputFormat, title: &str, records: &Value) {
+    let records = records.as_array().cloned().unwrap_or_default();
+    let mut headers = Vec::new();
+    let rows = records
+        .iter()
+        .map(|record| {
+            let mut fields = Vec::new();
+            flatten("", record, &mut fields);
+            if headers.is_empty() {
+                headers = fields.iter().map(|(key, _)| key.clone()).collect();
+            }
+            fields.into_iter().map(|(_, value)| value).collect()
+        })
+        .collect::<Vec<Vec<String>>>();
+    print_rows(format, title, &headers, &rows);
+}
+
+fn flatten(prefix: &str, value: &Value, fields: &mut Vec<(String, String)>) {
+    let key = |name: &str| {
+        if prefix.is_empty() {
+            name.to_owned()
+        } else {
+            format!("{}.{}", prefix, name)
+        }
+    };
+    match value {
+        Value::Object(object) => object
+            .iter()
+            .for_each(|(name, value)| flatten(&key(name), value, fields)),
+        Value::Array(array) => array
+            .iter()
+            .enumerate()
+            .for_each(|(index, value)| flatten(&key(&index.to_string()), value, fields)),
+        Value::Null => fields.push((prefix.to_owned(), String::new())),
+        Value::String(string) => fields.push((prefix.to_owned(), string.clone())),
+        _ => fields.push((prefix.to_owned(), value.to_string())),
+    }
+}
+
+/// Print `rows` under `headers` as an aligned table, or as CSV.
+pub fn print_rows(format: OutputFormat, title: &str, headers: &[String], rows: &[Vec<String>]) {
+    match format {
+        OutputFormat::Csv => {
+            let escape = |field: &String| {
+                if field.contains(',') || field.contains('"') {
+                    format!("\"{}\"", field.replace('"', "\"\""))
+                } else {
+                    field.clone()
+                }
+            };
+            println!(
+                "{}",
+                headers.iter().map(escape).collect::<Vec<_>>().join(",")
+            );
+            for row in rows.iter() {
+                println!("{}", row.iter().map(escape).collect::<Vec<_>>().join(","));
+            }
+            println!();
+        }
+        _ => {
+            let mut widths = headers.iter().map(String::len).collect::<Vec<_>>();
+            for row in rows.iter() {
+                for (width, field) in widths.iter_mut().zip(row.iter()) {
+                    *width = (*width).max(field.len());
+                }
+            }
+            let line = |fields: &[String]| {
+                fields
+                    .iter()
+                    .zip(widths.iter())
+                    .map(|(field, width)| format!("{:>width$}", field, width = width))
+                    .collect::<Vec<_>>()
+                    .join(" | ")
+            };
+            println!("{}", title);
+            println!("  {}", line(headers));
+            for row in rows.iter() {
+                println!("  {}", line(row));
+            }
+            println!();
+        }
+    }
+}
@@ -1,16 +1,103 @@
 use ckb_types::packed::{Block, Transaction};
+use ckb_types::prelude::*;
 use log::{error, info};
+use metrics::{gauge, timing};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::ops::Deref;
-use std::time::Duration;
+use std::sync::{Arc, Mutex};
+use std::thread::{sleep, spawn};
+use std::time::{Duration, Instant};
 
 use crate::account::Account;
+use crate::block_interval::{BlockInterval, BlockIntervalSampler};
+use crate::config::Url;
+use crate::global::BLOCK_ASSEMBLY_SAMPLES;
 use crate::net::Net;
+use crate::rpc::Jsonrpc;
+use crate::statistics::{correlation, Percentiles};
+use crate::util::call_until_ok;
+
+// How often the pool sampler polls `tx_pool_info`, off the mining path
+const POOL_SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+
+// Derives the seed of the sibling decisions from the configured one, so that they are independent
+// of the block intervals drawn from the same seed
+const SIBLING_SEED_SALT: u64 = 0x9e37_79b9_7f4a_7c15;
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct MinerConfig {
-    private_key: String,
+    pub(crate) private_key: String,
     block_time: u64,
+    #[serde(default)]
+    block_interval: BlockInterval,
+    /// `0` by default, or the index of a competing miner so that they draw different intervals
+    #[serde(default)]
+    seed: Option<u64>,
+    /// The probability, in `[0, 1]`, to generate a sibling block along with a block
+    #[serde(default)]
+    sibling_rate: f64,
+    #[serde(default)]
+    mining_policy: MiningPolicy,
+}
+
+/// When the dummy miner generates the next block.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+pub enum MiningPolicy {
+    /// Generate a block after every `block_interval`
+    Interval,
+    /// Generate a block as soon as the tx pool holds at least `threshold` proposed transactions.
+    /// Transactions are proposed only via blocks, so it still generates a block after every
+    /// `block_interval` otherwise.
+    ProposedTransactions { threshold: u64 },
+    /// Generate a block as soon as the total size of the tx pool reaches `threshold` bytes, or
+    /// after every `block_interval` otherwise
+    PoolBytes { threshold: u64 },
+    /// Generate blocks one after another without sleeping, to find the node's raw
+    /// block-processing ceiling
+    MaxRate,
+}
+
+impl Default for MiningPolicy {
+    fn default() -> Self {
+        MiningPolicy::Interval
+    }
+}
+
+/// A miner which fetches block templates from and submits blocks to its own endpoint only, so
+/// that it competes with the other miners and natural forks and uncles happen.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct CompetingMinerConfig {
+    pub rpc_url: Url,
+    #[serde(flatten)]
+    pub miner: MinerConfig,
+}
+
+/// The latencies of assembling and submitting a block, and the latest tx pool size sampled by
+/// `Miner::spawn_pool_sampler` at that time
+#[derive(Debug, Clone)]
+pub struct BlockAssemblySample {
+    /// The endpoint the block is assembled by, as competing miners assemble on their own
+    pub endpoint: String,
+    pub get_block_template: Duration,
+    pub submit_block: Duration,
+    pub pool_transactions: Option<u64>,
+    pub pool_size: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct BlockAssemblyMetrics {
+    endpoint: String,
+    blocks: u64,
+    get_block_template_us: Percentiles,
+    submit_block_us: Percentiles,
+    /// The pool fields are `null` if the pool was never sampled
+    average_pool_transactions: Option<u64>,
+    average_pool_size: Option<u64>,
+    /// Pearson correlation between the `get_block_template` latency and the pool transactions
+    get_block_template_pool_correlation: Option<f64>,
 }
 
 #[derive(Clone)]
@@ -18,34 +105,206 @@ pub struct Miner {
     net: Net,
     account: Account,
     pub block_time: Duration,
+    block_interval: BlockInterval,
+    seed: u64,
+    sibling_rate: f64,
+    mining_policy: MiningPolicy,
+    /// The latest `(transactions, size)` of the tx pool, see `spawn_pool_sampler`
+    pool: Arc<Mutex<Option<(u64, u64)>>>,
+    rng: Arc<Mutex<StdRng>>,
 }
 
 impl Miner {
     pub fn new(miner_config: &MinerConfig, rpc_urls: Vec<&str>) -> Self {
         let net = Net::connect_all(rpc_urls);
+        if miner_config.sibling_rate > 0.0 && net.endpoints().len() < 2 {
+            prompt_and_exit!(
+                "sibling_rate {} requires at least 2 endpoints to submit the siblings to, got {}",
+                miner_config.sibling_rate,
+                net.endpoints().len()
+            );
+        }
         let account = Account::new(&miner_config.private_key);
         let block_time = Duration::from_millis(miner_config.block_time);
         Self {
             net,
             account,
             block_time,
+            block_interval: miner_config.block_interval.clone(),
+            seed: miner_config.seed.unwrap_or(0),
+            sibling_rate: miner_config.sibling_rate.max(0.0).min(1.0),
+            mining_policy: miner_config.mining_policy,
+            pool: Arc::new(Mutex::new(None)),
+            rng: Arc::new(Mutex::new(StdRng::seed_from_u64(
+                miner_config.seed.unwrap_or(0) ^ SIBLING_SEED_SALT,
+            ))),
+        }
+    }
+
+    /// Return a sampler of the intervals between blocks, which yields the same sequence for the
+    /// same configured seed.
+    pub fn block_intervals(&self) -> BlockIntervalSampler {
+        BlockIntervalSampler::new(self.block_time, self.block_interval.clone(), self.seed)
+    }
+
+    /// Block until it is time to generate the next block according to the mining policy.
+    pub fn wait_next_block(&self, block_intervals: &mut BlockIntervalSampler) {
+        const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+        let deadline = Instant::now() + block_intervals.next_interval();
+        let is_pool_ready = || match self.mining_policy {
+            MiningPolicy::ProposedTransactions { threshold } => self
+                .net
+                .tx_pool_info()
+                .map(|tx_pool_info| tx_pool_info.proposed.value() >= threshold)
+                .unwrap_or(false),
+            MiningPolicy::PoolBytes { threshold } => self
+                .net
+                .tx_pool_info()
+                .map(|tx_pool_info| tx_pool_info.total_tx_size.value() >= threshold)
+                .unwrap_or(false),
+            MiningPolicy::Interval | MiningPolicy::MaxRate => false,
+        };
+        match self.mining_policy {
+            MiningPolicy::MaxRate => {}
+            MiningPolicy::Interval => sleep(deadline.saturating_duration_since(Instant::now())),
+            MiningPolicy::ProposedTransactions { .. } | MiningPolicy::PoolBytes { .. } => {
+                while Instant::now() < deadline && !is_pool_ready() {
+                    sleep(POLL_INTERVAL);
+                }
+            }
         }
     }
 
-    // TODO multiple miners
     pub fn generate_block(&self) -> Option<u64> {
-        let template = self.net.get_block_template(None, None, None);
-        let work_id = template.work_id.value();
+        let start = Instant::now();
+        let template = match self.net.get_block_template(None, None, None) {
+            Ok(template) => template,
+            Err(err) => {
+                error!("get_block_template from {}, error: {}", self.net.uri(), err);
+                return None;
+            }
+        };
+        let get_block_template_end = Instant::now();
+        let pool = *self.pool.lock().unwrap();
+        let work_id = template.work_id.value().to_string();
         let block_number = template.number.value();
         let block: Block = template.into();
 
-        if let Some(block_hash) = self.net.submit_block(work_id.to_string(), block.into()) {
-            info!("submit block  #{} {:#x}", block_number, block_hash);
-            Some(block_number)
-        } else {
-            error!("submit block  #{} None", block_number);
-            None
+        if self.should_generate_sibling() {
+            // The sibling differs from the block only in timestamp. Submit it to another endpoint,
+            // `Miner::new` ensures there is one, so that the two blocks race and one of them
+            // becomes an uncle.
+            let view = block.clone().into_view();
+            let sibling = view
+                .as_advanced_builder()
+                .timestamp((view.timestamp() + 1).pack())
+                .build();
+            let rpc = self.net.endpoints().last().expect("at least two endpoints");
+            self.submit_block(rpc, work_id.clone(), sibling.data(), block_number);
         }
+
+        let submit_block_start = Instant::now();
+        let submitted = self.submit_block(&self.net, work_id, block, block_number);
+        let submit_block_end = Instant::now();
+
+        timing!(
+            "tps_bench.miner.get_block_template",
+            start,
+            get_block_template_end
+        );
+        timing!(
+            "tps_bench.miner.submit_block",
+            submit_block_start,
+            submit_block_end
+        );
+        gauge!("tps_bench.miner.tip_number", block_number as i64);
+        // Recorded only while a benchmark is running, see `BLOCK_ASSEMBLY_SAMPLES`
+        if let Some(samples) = BLOCK_ASSEMBLY_SAMPLES.lock().unwrap().as_mut() {
+            samples.push(BlockAssemblySample {
+                endpoint: self.net.uri().clone(),
+                get_block_template: get_block_template_end - start,
+                submit_block: submit_block_end - submit_block_start,
+                pool_transactions: pool.map(|(transactions, _)| transactions),
+                pool_size: pool.map(|(_, size)| size),
+            });
+        }
+
+        submitted
+    }
+
+    /// Poll `tx_pool_info` in the background for the pool gauges and the block assembly samples,
+    /// so that the mining path costs no extra round-trip.
+    pub fn spawn_pool_sampler(&self) {
+        let net = self.net.clone();
+        let pool = Arc::clone(&self.pool);
+        spawn(move || loop {
+            match net.tx_pool_info() {
+                Ok(tx_pool_info) => {
+                    let transactions = tx_pool_info.pending.value() + tx_pool_info.proposed.value();
+                    let size = tx_pool_info.total_tx_size.value();
+                    gauge!("tps_bench.miner.pool_transactions", transactions as i64);
+                    gauge!(
+                        "tps_bench.miner.pool_pending",
+                        tx_pool_info.pending.value() as i64
+                    );
+                    gauge!(
+                        "tps_bench.miner.pool_proposed",
+                        tx_pool_info.proposed.value() as i64
+                    );
+                    gauge!("tps_bench.miner.pool_size", size as i64);
+                    *pool.lock().unwrap() = Some((transactions, size));
+                }
+                Err(err) => error!("tx_pool_info from {}, error: {}", net.uri(), err),
+            }
+            sleep(POOL_SAMPLE_INTERVAL);
+        });
+    }
+
+    fn should_generate_sibling(&self) -> bool {
+        self.sibling_rate > 0.0 && self.rng.lock().unwrap().gen_bool(self.sibling_rate)
+    }
+
+    fn submit_block(
+        &self,
+        rpc: &Jsonrpc,
+        work_id: String,
+        block: Block,
+        block_number: u64,
+    ) -> Option<u64> {
+        // Competing miners may submit stale blocks, just log the error rather than panic.
+        match rpc.submit_block(work_id, block.into()) {
+            Ok(Some(block_hash)) => {
+                info!(
+                    "submit block  #{} {:#x} to {}",
+                    block_number,
+                    block_hash,
+                    rpc.uri()
+                );
+                Some(block_number)
+            }
+            Ok(None) => {
+                error!("submit block  #{} None to {}", block_number, rpc.uri());
+                None
+            }
+            Err(err) => {
+                error!(
+                    "submit block  #{} to {}, error: {}",
+                    block_number,
+                    rpc.uri(),
+                    err
+                );
+                None
+            }
+        }
+    }
+
+    /// Create the `index`-th competing miner. Its seed defaults to `index`, so that the competing
+    /// miners draw independent block intervals.
+    pub fn from_competing(config: &CompetingMinerConfig, index: usize) -> Self {
+        let mut miner_config = config.miner.clone();
+        miner_config.seed = Some(miner_config.seed.unwrap_or(index as u64));
+        Self::new(&miner_config, vec![config.rpc_url.as_str()])
     }
 
     /// Run a miner to generate the given number of blocks.
@@ -60,12 +319,11 @@ impl Miner {
         let configured_miner_lock_script = self.lock_script();
         let block_assembler_lock_script = {
             loop {
-                let cellbase: Transaction = self
-                    .net
-                    .get_block_template(None, None, None)
-                    .cellbase
-                    .data
-                    .into();
+                let template = call_until_ok("get_block_template", || {
+                    self.net.get_block_template(None, None, None)
+                })
+                .unwrap_or_else(|err| panic!("get_block_template error: {}", err));
+                let cellbase: Transaction = template.cellbase.data.into();
                 if let Some(output) = cellbase.into_view().output(0) {
                     break output.lock();
                 }
@@ -76,6 +334,71 @@ impl Miner {
     }
 }
 
+impl BlockAssemblyMetrics {
+    /// Evaluate the samples of every endpoint separately.
+    pub fn eval_per_endpoint(samples: &[BlockAssemblySample]) -> Vec<Self> {
+        let mut samples_by_endpoint = BTreeMap::<&str, Vec<BlockAssemblySample>>::new();
+        for sample in samples {
+            samples_by_endpoint
+                .entry(&sample.endpoint)
+                .or_default()
+                .push(sample.clone());
+        }
+        samples_by_endpoint
+            .into_iter()
+            .map(|(endpoint, samples)| Self::eval(endpoint, &samples))
+            .collect()
+    }
+
+    fn eval(endpoint: &str, samples: &[BlockAssemblySample]) -> Self {
+        let blocks = samples.len() as u64;
+        let get_block_template_us = samples
+            .iter()
+            .map(|sample| sample.get_block_template.as_micros() as u64)
+            .collect::<Vec<_>>();
+        let submit_block_us = samples
+            .iter()
+            .map(|sample| sample.submit_block.as_micros() as u64)
+            .collect::<Vec<_>>();
+        // Only the blocks with the pool sampled
+        let pooled = samples
+            .iter()
+            .filter_map(|sample| {
+                Some((
+                    sample.get_block_template.as_micros() as f64,
+                    sample.pool_transactions?,
+                    sample.pool_size?,
+                ))
+            })
+            .collect::<Vec<_>>();
+        let (average_pool_transactions, average_pool_size, get_block_template_pool_correlation) =
+            if pooled.is_empty() {
+                (None, None, None)
+            } else {
+                let count = pooled.len() as u64;
+                let latencies = pooled.iter().map(|(us, _, _)| *us).collect::<Vec<_>>();
+                let transactions = pooled
+                    .iter()
+                    .map(|(_, txs, _)| *txs as f64)
+                    .collect::<Vec<_>>();
+                (
+                    Some(pooled.iter().map(|(_, txs, _)| txs).sum::<u64>() / count),
+                    Some(pooled.iter().map(|(_, _, size)| size).sum::<u64>() / count),
+                    Some(correlation(&latencies, &transactions)),
+                )
+            };
+        Self {
+            endpoint: endpoint.to_owned(),
+            blocks,
+            get_block_template_us: Percentiles::new(&get_block_template_us),
+            submit_block_us: Percentiles::new(&submit_block_us),
+            average_pool_transactions,
+            average_pool_size,
+            get_block_template_pool_correlation,
+        }
+    }
+}
+
 impl Deref for Miner {
     type Target = Account;
 
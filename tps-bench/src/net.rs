@@ -1,4 +1,5 @@
 use crate::global::CONFIRMATION_BLOCKS;
+use crate::rpc::{RpcError, RPC_BATCH_SIZE};
 use crate::Jsonrpc;
 use ckb_types::core::{BlockNumber, BlockView, HeaderView};
 use std::ops::Deref;
@@ -26,66 +27,92 @@ impl Net {
         &self.endpoints
     }
 
-    pub fn get_confirmed_tip_number(&self) -> BlockNumber {
-        self.get_confirmed_tip_header().number()
+    pub fn get_confirmed_tip_number(&self) -> Result<BlockNumber, RpcError> {
+        Ok(self.get_confirmed_tip_header()?.number())
     }
 
-    pub fn get_confirmed_tip_block(&self) -> BlockView {
-        let header = self.get_confirmed_tip_header();
-        let block = self.get_block(header.hash()).unwrap();
-        block.into()
+    pub fn get_confirmed_tip_block(&self) -> Result<BlockView, RpcError> {
+        let header = self.get_confirmed_tip_header()?;
+        let block = self.get_block(header.hash())?.ok_or_else(|| {
+            RpcError::NotFound(format!(
+                "rpc.get_block({}, {:#x})",
+                self.endpoints[0].uri(),
+                header.hash()
+            ))
+        })?;
+        Ok(block.into())
     }
 
-    pub fn get_confirmed_tip_header(&self) -> HeaderView {
-        let unconfirmed = self.get_unconfirmed_fixed_tip_header();
+    pub fn get_confirmed_tip_header(&self) -> Result<HeaderView, RpcError> {
+        let unconfirmed = self.get_unconfirmed_fixed_tip_header()?;
         let unconfirmed_number = unconfirmed.number();
         let confirmed_number =
             unconfirmed_number.saturating_sub(*CONFIRMATION_BLOCKS.lock().unwrap());
-        self.get_header_by_number(confirmed_number)
-            .unwrap_or_else(|| {
-                panic!(
+        let header = self
+            .get_header_by_number(confirmed_number)?
+            .ok_or_else(|| {
+                RpcError::NotFound(format!(
                     "rpc.get_header_by_number({}, unconfirmed={}, confirmed={})",
                     self.endpoints[0].uri(),
                     unconfirmed_number,
                     confirmed_number
-                )
-            })
-            .into()
+                ))
+            })?;
+        Ok(header.into())
     }
 
-    fn get_unconfirmed_fixed_tip_header(&self) -> HeaderView {
-        let tip_number = self.endpoints[0].get_tip_block_number();
+    fn get_unconfirmed_fixed_tip_header(&self) -> Result<HeaderView, RpcError> {
+        let tip_number = self.endpoints[0].get_tip_block_number()?;
         for number in (0..=tip_number).rev() {
-            if let Some(header) = self.endpoints[0].get_header_by_number(number) {
-                let is_fixed = self.endpoints[1..self.endpoints.len()].iter().all(|rpc| {
-                    rpc.get_header_by_number(number)
-                        .map(|h| h == header)
-                        .unwrap_or(false)
-                });
-                if is_fixed {
-                    return header.into();
-                }
-            };
+            if let Some(header) = self.get_fixed_header(number)? {
+                return Ok(header);
+            }
         }
         unreachable!()
     }
 
-    pub fn get_fixed_header(&self, number: BlockNumber) -> Option<HeaderView> {
-        if let Some(header) = self.endpoints[0].get_header_by_number(number) {
-            let is_fixed = self.endpoints[1..self.endpoints.len()].iter().all(|rpc| {
-                rpc.get_header_by_number(number)
-                    .map(|h| h == header)
-                    .unwrap_or(false)
-            });
-            if is_fixed {
-                return Some(header.into());
+    /// Return the header of `number` if all the endpoints agree on it.
+    pub fn get_fixed_header(&self, number: BlockNumber) -> Result<Option<HeaderView>, RpcError> {
+        if let Some(header) = self.endpoints[0].get_header_by_number(number)? {
+            for rpc in self.endpoints[1..self.endpoints.len()].iter() {
+                if rpc.get_header_by_number(number)?.as_ref() != Some(&header) {
+                    return Ok(None);
+                }
             }
+            return Ok(Some(header.into()));
         };
-        None
+        Ok(None)
+    }
+
+    /// Iterate the blocks in `[from_number, end_number]` in order, fetched in JSON-RPC batches.
+    pub fn get_blocks_in_range(
+        &self,
+        from_number: BlockNumber,
+        end_number: BlockNumber,
+    ) -> impl Iterator<Item = Result<BlockView, RpcError>> + '_ {
+        (from_number..=end_number)
+            .step_by(RPC_BATCH_SIZE)
+            .flat_map(move |start| {
+                let end = end_number.min(start + RPC_BATCH_SIZE as BlockNumber - 1);
+                let numbers = (start..=end).collect::<Vec<_>>();
+                let blocks = match self.get_blocks_by_number(&numbers) {
+                    Ok(blocks) => blocks,
+                    Err(err) => return vec![Err(err)],
+                };
+                blocks
+                    .into_iter()
+                    .zip(numbers)
+                    .map(|(block, number)| {
+                        block.map(Into::into).ok_or_else(|| {
+                            RpcError::NotFound(format!("get_block_by_number({})", number))
+                        })
+                    })
+                    .collect()
+            })
     }
 
-    pub fn get_network_nodes(&self) -> u64 {
-        self.endpoints[0].get_peers().len() as u64 + 1 as u64
+    pub fn get_network_nodes(&self) -> Result<u64, RpcError> {
+        Ok(self.endpoints[0].get_peers()?.len() as u64 + 1 as u64)
     }
 
     pub fn get_bench_nodes(&self) -> u64 {
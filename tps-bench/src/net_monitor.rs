@@ -1,6 +1,11 @@
+use crate::global::BLOCK_PROPAGATION;
 use crate::net::Net;
-use ckb_types::core::BlockView;
-use log::info;
+use crate::propagation::BlockPropagationMetrics;
+use crate::rpc::RpcError;
+use crate::util::call_until_ok;
+use ckb_chain_spec::consensus::Consensus;
+use ckb_types::core::{BlockNumber, BlockView};
+use log::{info, warn};
 use serde_derive::{Deserialize, Serialize};
 use serde_json::json;
 use std::cmp::max;
@@ -39,6 +44,134 @@ pub struct Metrics {
     network_nodes: u64,
     bench_nodes: u64,
     total_transactions_size: u64,
+    uncles_count: u64,
+    uncle_rate: f64,
+    /// Per-node block propagation delays, empty if the propagation is not monitored
+    #[serde(default)]
+    block_propagation: Vec<BlockPropagationMetrics>,
+    #[serde(default)]
+    block_utilization: BlockUtilization,
+}
+
+/// How close the evaluated blocks are to the block limits of the chain under test.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct BlockUtilization {
+    /// Where the limits come from, `block_template` of the chain under test, or
+    /// `default_consensus` if the node does not serve block templates
+    limits_source: String,
+    /// Serialized block size without uncle proposals, against the bytes limit
+    bytes: ResourceUtilization,
+    /// Proposals of the block itself, against the proposals limit
+    proposals: ResourceUtilization,
+    /// Uncles, against the uncles count limit
+    uncles: ResourceUtilization,
+    cycles_limit: u64,
+    /// Always `null`, the cycles of a committed block are not exposed via RPC
+    cycles: Option<ResourceUtilization>,
+    /// The ratio of the blocks containing only the cellbase
+    empty_block_ratio: f64,
+}
+
+// The block limits as `(bytes, proposals, uncles, cycles)`
+type BlockLimits = (u64, u64, u64, u64);
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct ResourceUtilization {
+    limit: u64,
+    average: f64,
+    max: u64,
+    average_utilization: f64,
+    max_utilization: f64,
+}
+
+impl ResourceUtilization {
+    fn new(limit: u64, samples: &[u64]) -> Self {
+        let average = samples.iter().sum::<u64>() as f64 / samples.len().max(1) as f64;
+        let max = samples.iter().max().cloned().unwrap_or(0);
+        let utilization = |value: f64| {
+            if limit == 0 {
+                0.0
+            } else {
+                value / limit as f64
+            }
+        };
+        Self {
+            limit,
+            average,
+            max,
+            average_utilization: utilization(average),
+            max_utilization: utilization(max as f64),
+        }
+    }
+}
+
+impl BlockUtilization {
+    fn eval(net: &Net, blocks: &[BlockUsage]) -> Self {
+        let (limits_source, (bytes_limit, proposals_limit, uncles_limit, cycles_limit)) =
+            match block_limits(net) {
+                Ok(limits) => ("block_template", limits),
+                Err(err) => {
+                    warn!(
+                        "get_block_template({}) error: {}, use the default consensus limits",
+                        net.uri(),
+                        err
+                    );
+                    let consensus = Consensus::default();
+                    let limits = (
+                        consensus.max_block_bytes(),
+                        consensus.max_block_proposals_limit(),
+                        consensus.max_uncles_num() as u64,
+                        consensus.max_block_cycles(),
+                    );
+                    ("default_consensus", limits)
+                }
+            };
+        let samples = |usage: fn(&BlockUsage) -> u64| blocks.iter().map(usage).collect::<Vec<_>>();
+        let empty_blocks = blocks
+            .iter()
+            .filter(|block| block.transactions <= 1)
+            .count();
+        Self {
+            limits_source: limits_source.to_owned(),
+            bytes: ResourceUtilization::new(bytes_limit, &samples(|b| b.bytes)),
+            proposals: ResourceUtilization::new(proposals_limit, &samples(|b| b.proposals)),
+            uncles: ResourceUtilization::new(uncles_limit, &samples(|b| b.uncles)),
+            cycles_limit,
+            cycles: None,
+            empty_block_ratio: empty_blocks as f64 / blocks.len().max(1) as f64,
+        }
+    }
+}
+
+// The block template carries the limits of the node's consensus
+fn block_limits(net: &Net) -> Result<BlockLimits, RpcError> {
+    let template = net.get_block_template(None, None, None)?;
+    Ok((
+        template.bytes_limit.value(),
+        template.proposals_limit.value(),
+        template.uncles_count_limit.value(),
+        template.cycles_limit.value(),
+    ))
+}
+
+/// The resource usage of a block, kept instead of the block to save memory on long ranges
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct BlockUsage {
+    pub transactions: u64,
+    pub bytes: u64,
+    pub proposals: u64,
+    pub uncles: u64,
+}
+
+impl BlockUsage {
+    pub fn new(block: &BlockView) -> Self {
+        Self {
+            transactions: block.transactions().len() as u64,
+            bytes: block.data().serialized_size_without_uncle_proposals() as u64,
+            proposals: block.data().proposals().len() as u64,
+            uncles: block.data().uncles().len() as u64,
+        }
+    }
 }
 
 pub fn wait_network_stabled(net: &Net, evaluation: MethodToEvalNetStable) -> Metrics {
@@ -68,9 +201,9 @@ pub fn wait_network_txpool_empty(net: &Net) {
 }
 
 fn wait_custom_blocks_elapsed(net: &Net, window: u64, warmup: u64) -> Metrics {
-    let current_tip_number = net.get_confirmed_tip_number();
+    let current_tip_number = confirmed_tip_number(net);
     let (mut last_print, start_time) = (Instant::now(), Instant::now());
-    while current_tip_number + warmup > net.get_confirmed_tip_number() {
+    while current_tip_number + warmup > confirmed_tip_number(net) {
         if last_print.elapsed() >= Duration::from_secs(60) {
             last_print = Instant::now();
             info!(
@@ -83,9 +216,9 @@ fn wait_custom_blocks_elapsed(net: &Net, window: u64, warmup: u64) -> Metrics {
     }
     info!("complete warmup, took {:?}", start_time.elapsed());
 
-    let current_tip_number = net.get_confirmed_tip_number();
+    let current_tip_number = confirmed_tip_number(net);
     let (mut last_print, start_time) = (Instant::now(), Instant::now());
-    while current_tip_number + window > net.get_confirmed_tip_number() {
+    while current_tip_number + window > confirmed_tip_number(net) {
         if last_print.elapsed() >= Duration::from_secs(60) {
             last_print = Instant::now();
             info!(
@@ -98,19 +231,31 @@ fn wait_custom_blocks_elapsed(net: &Net, window: u64, warmup: u64) -> Metrics {
     }
     info!("complete evaluation, took {:?}", start_time.elapsed());
 
-    Metrics::eval_blocks(net, current_tip_number, current_tip_number + window)
+    eval_blocks_until_ok(net, current_tip_number, current_tip_number + window)
 }
 
 fn wait_recent_blocktxns_nearly(net: &Net, window: u64, margin: u64) -> Metrics {
     info!("[START] net_monitor::wait_recent_blocktxns_nearly");
     let mut queue = VecDeque::with_capacity(window as usize);
-    queue.push_back(net.get_confirmed_tip_block());
+    queue.push_back(
+        call_until_ok("net.get_confirmed_tip_block", || {
+            net.get_confirmed_tip_block()
+        })
+        .unwrap_or_else(|err| panic!("net.get_confirmed_tip_block error: {}", err)),
+    );
     loop {
         loop {
-            let tip_number = net.get_confirmed_tip_number();
+            let tip_number = confirmed_tip_number(net);
             let back = queue.back().unwrap();
             if tip_number > back.number() {
-                let next_block = net.get_block_by_number(back.number() + 1).unwrap().into();
+                let next_number = back.number() + 1;
+                let next_block: BlockView = call_until_ok("net.get_block_by_number", || {
+                    net.get_block_by_number(next_number)?.ok_or_else(|| {
+                        RpcError::NotFound(format!("get_block_by_number({})", next_number))
+                    })
+                })
+                .unwrap_or_else(|err| panic!("net.get_block_by_number error: {}", err))
+                .into();
                 while queue.len() >= window as usize {
                     queue.pop_front();
                 }
@@ -124,7 +269,7 @@ fn wait_recent_blocktxns_nearly(net: &Net, window: u64, margin: u64) -> Metrics
         if queue.len() >= window as usize {
             let from_number = queue.pop_front().unwrap().number();
             let end_number = queue.pop_back().unwrap().number();
-            let metrics = Metrics::eval_blocks(net, from_number, end_number);
+            let metrics = eval_blocks_until_ok(net, from_number, end_number);
             info!("[metrics] {}", json!(metrics));
 
             let mintxns = queue.iter().map(|b| b.transactions().len()).min().unwrap();
@@ -138,7 +283,7 @@ fn wait_recent_blocktxns_nearly(net: &Net, window: u64, margin: u64) -> Metrics
 
 fn wait_duration_time_elapsed(net: &Net, duration_time: u64) -> Metrics {
     info!("[START] net_monitor::wait_duration_time_elapsed");
-    let first_tip_number = net.get_confirmed_tip_number();
+    let first_tip_number = confirmed_tip_number(net);
     let (start_time, mut last_print) = (Instant::now(), Instant::now());
     while start_time.elapsed() <= Duration::from_secs(duration_time) {
         if last_print.elapsed() >= Duration::from_secs(60) {
@@ -151,13 +296,29 @@ fn wait_duration_time_elapsed(net: &Net, duration_time: u64) -> Metrics {
         }
         sleep(Duration::from_secs(1));
     }
-    let last_tip_number = net.get_confirmed_tip_number();
-    Metrics::eval_blocks(net, first_tip_number, last_tip_number)
+    let last_tip_number = confirmed_tip_number(net);
+    eval_blocks_until_ok(net, first_tip_number, last_tip_number)
+}
+
+// An unavailable node delays rather than aborts the monitoring
+fn confirmed_tip_number(net: &Net) -> BlockNumber {
+    call_until_ok("net.get_confirmed_tip_number", || {
+        net.get_confirmed_tip_number()
+    })
+    .unwrap_or_else(|err| panic!("net.get_confirmed_tip_number error: {}", err))
+}
+
+fn eval_blocks_until_ok(net: &Net, from_number: BlockNumber, end_number: BlockNumber) -> Metrics {
+    call_until_ok("Metrics::eval_blocks", || {
+        Metrics::eval_blocks(net, from_number, end_number)
+    })
+    .unwrap_or_else(|err| panic!("Metrics::eval_blocks error: {}", err))
 }
 
 fn is_network_txpool_empty(net: &Net) -> bool {
     for rpc in net.endpoints() {
-        let tx_pool_info = rpc.tx_pool_info();
+        let tx_pool_info = call_until_ok("rpc.tx_pool_info", || rpc.tx_pool_info())
+            .unwrap_or_else(|err| panic!("rpc.tx_pool_info error: {}", err));
         if tx_pool_info.pending.value() != 0 || tx_pool_info.proposed.value() != 0 {
             return false;
         }
@@ -166,26 +327,61 @@ fn is_network_txpool_empty(net: &Net) -> bool {
 }
 
 impl Metrics {
-    pub fn eval_blocks(net: &Net, from_number: u64, end_number: u64) -> Self {
-        let network_nodes = net.get_network_nodes();
+    pub fn tps(&self) -> u64 {
+        self.tps
+    }
+
+    pub fn block_range(&self) -> (u64, u64) {
+        (self.start_block_number, self.end_block_number)
+    }
+
+    pub fn eval_blocks(net: &Net, from_number: u64, end_number: u64) -> Result<Self, RpcError> {
+        let blocks = net
+            .get_blocks_in_range(from_number, end_number)
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::eval_fetched_blocks(net, from_number, end_number, &blocks)
+    }
+
+    /// Same as `eval_blocks`, over the already fetched blocks of `[from_number, end_number]`
+    pub fn eval_fetched_blocks(
+        net: &Net,
+        from_number: u64,
+        end_number: u64,
+        blocks: &[BlockView],
+    ) -> Result<Self, RpcError> {
+        let network_nodes = net.get_network_nodes()?;
         let bench_nodes = net.get_bench_nodes();
 
         let mut totaltxns: usize = 0;
         let mut total_transactions_size: u64 = 0;
-        for number in from_number..=end_number {
-            let block: BlockView = net.get_block_by_number(number).unwrap().into();
+        let mut uncles_count: u64 = 0;
+        let mut block_usages = Vec::new();
+        let (mut front_timestamp, mut back_timestamp) = (0, 0);
+        for block in blocks {
+            if block.number() == from_number {
+                front_timestamp = block.timestamp();
+            }
+            back_timestamp = block.timestamp();
             totaltxns += block.transactions().len();
-            total_transactions_size += eval_total_tx_size_in_block(&block);
+            total_transactions_size += eval_total_tx_size_in_block(block);
+            uncles_count += block.data().uncles().len() as u64;
+            block_usages.push(BlockUsage::new(block));
         }
+        let block_utilization = BlockUtilization::eval(net, &block_usages);
 
         let blocks_count: u64 = end_number - from_number + 1;
-        let front: BlockView = net.get_block_by_number(from_number).unwrap().into();
-        let back: BlockView = net.get_block_by_number(end_number).unwrap().into();
         let average_block_transactions = (totaltxns / blocks_count as usize) as u64;
-        let elapsed_ms = back.timestamp().saturating_sub(front.timestamp());
+        let elapsed_ms = back_timestamp.saturating_sub(front_timestamp);
         let average_block_time_ms = max(1, elapsed_ms / blocks_count);
         let tps = (totaltxns as f64 * 1000.0 / elapsed_ms as f64) as u64;
-        Metrics {
+        // The ratio of uncles to all the blocks mined in the window
+        let uncle_rate = uncles_count as f64 / (blocks_count + uncles_count) as f64;
+        let block_propagation =
+            BLOCK_PROPAGATION
+                .lock()
+                .unwrap()
+                .metrics(net, from_number, end_number);
+        Ok(Metrics {
             tps,
             average_block_time_ms,
             average_block_transactions,
@@ -194,10 +390,52 @@ impl Metrics {
             network_nodes,
             bench_nodes,
             total_transactions_size,
-        }
+            uncles_count,
+            uncle_rate,
+            block_propagation,
+            block_utilization,
+        })
     }
 }
 
+/// Split `[from_number, end_number]` into `sub_windows` consecutive sub-windows and evaluate
+/// the TPS of each, counted the same as `Metrics::eval_blocks`.
+pub fn eval_sub_window_tps(
+    net: &Net,
+    from_number: u64,
+    end_number: u64,
+    sub_windows: u64,
+) -> Result<Vec<u64>, RpcError> {
+    let sub_windows = sub_windows.min(end_number.saturating_sub(from_number));
+    if sub_windows == 0 {
+        return Ok(Vec::new());
+    }
+
+    let (mut timestamps, mut transactions) = (Vec::new(), Vec::new());
+    for block in net.get_blocks_in_range(from_number, end_number) {
+        let block = block?;
+        timestamps.push(block.timestamp());
+        transactions.push(block.transactions().len() as u64);
+    }
+
+    let span = end_number - from_number;
+    let boundaries = (0..=sub_windows)
+        .map(|index| (index * span / sub_windows) as usize)
+        .collect::<Vec<_>>();
+    Ok(boundaries
+        .windows(2)
+        .filter_map(|pair| {
+            let (start, end) = (pair[0], pair[1]);
+            let elapsed_ms = timestamps[end].saturating_sub(timestamps[start]);
+            if elapsed_ms == 0 {
+                return None;
+            }
+            let txns = transactions[start + 1..=end].iter().sum::<u64>();
+            Some((txns as f64 * 1000.0 / elapsed_ms as f64) as u64)
+        })
+        .collect())
+}
+
 fn eval_total_tx_size_in_block(block: &BlockView) -> u64 {
     block
         .transactions()
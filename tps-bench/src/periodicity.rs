@@ -0,0 +1,149 @@
+use crate::statistics::autocorrelation;
+
+use ckb_chain_spec::consensus::Consensus;
+use ckb_types::core::{BlockNumber, BlockView};
+use serde_derive::{Deserialize, Serialize};
+
+// The longest period searched for, in blocks
+const MAX_PERIOD: usize = 100;
+
+/// The periodicity of the per-block transaction counts, compared with the proposal window.
+///
+/// A transaction proposed in block `n` can only be committed in `[n + closest, n + farthest]`,
+/// so bursts of proposals may recur in the committed transaction counts every few windows.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Periodicity {
+    start_block_number: BlockNumber,
+    end_block_number: BlockNumber,
+    mean_block_transactions: f64,
+    /// The lag of the highest autocorrelation peak, `None` if the counts have no periodicity
+    dominant_period: Option<u64>,
+    autocorrelation: f64,
+    /// Half of the peak-to-peak of the counts averaged by the phase within the dominant period
+    amplitude: f64,
+    proposal_window_closest: BlockNumber,
+    proposal_window_farthest: BlockNumber,
+    /// Whether the dominant period is a multiple of the farthest proposal window, give or take
+    /// one block
+    aligned_with_proposal_window: bool,
+}
+
+/// The proposal window of the default consensus, as `(closest, farthest)`
+pub fn default_proposal_window() -> (BlockNumber, BlockNumber) {
+    let window = Consensus::default().tx_proposal_window();
+    (window.closest(), window.farthest())
+}
+
+impl Periodicity {
+    /// Evaluate from the fetched blocks of `[from_number, end_number]`
+    pub fn eval(
+        blocks: &[BlockView],
+        from_number: BlockNumber,
+        end_number: BlockNumber,
+        proposal_window: (BlockNumber, BlockNumber),
+    ) -> Self {
+        let counts = blocks
+            .iter()
+            // Exclude the cellbase
+            .map(|block| block.transactions().len().saturating_sub(1) as f64)
+            .collect::<Vec<_>>();
+        let mean_block_transactions = counts.iter().sum::<f64>() / counts.len().max(1) as f64;
+
+        let (closest, farthest) = proposal_window;
+        let (dominant_period, autocorrelation, amplitude, aligned_with_proposal_window) =
+            match dominant_period(&counts) {
+                Some((period, correlation)) => {
+                    let multiple = (period as f64 / farthest.max(1) as f64).round() as u64;
+                    let aligned =
+                        multiple >= 1 && (period as i64 - (multiple * farthest) as i64).abs() <= 1;
+                    (
+                        Some(period as u64),
+                        correlation,
+                        phase_amplitude(&counts, period),
+                        aligned,
+                    )
+                }
+                None => (None, 0.0, 0.0, false),
+            };
+        Self {
+            start_block_number: from_number,
+            end_block_number: end_number,
+            mean_block_transactions,
+            dominant_period,
+            autocorrelation,
+            amplitude,
+            proposal_window_closest: closest,
+            proposal_window_farthest: farthest,
+            aligned_with_proposal_window,
+        }
+    }
+}
+
+// Take the highest local maximum of the autocorrelation of `counts` as the dominant period,
+// returned with its autocorrelation
+fn dominant_period(counts: &[f64]) -> Option<(usize, f64)> {
+    let max_period = MAX_PERIOD.min(counts.len() / 2);
+    let correlations = (0..=max_period + 1)
+        .map(|lag| autocorrelation(counts, lag))
+        .collect::<Vec<_>>();
+    (2..=max_period)
+        .filter(|&lag| {
+            correlations[lag] > 0.0
+                && correlations[lag] >= correlations[lag - 1]
+                && correlations[lag] >= correlations[lag + 1]
+        })
+        .max_by(|&a, &b| {
+            correlations[a]
+                .partial_cmp(&correlations[b])
+                .expect("autocorrelation is not NaN")
+        })
+        .map(|period| (period, correlations[period]))
+}
+
+// Average the samples by their phase within `period`, then take the half of the peak-to-peak
+fn phase_amplitude(samples: &[f64], period: usize) -> f64 {
+    let mut sums = vec![(0.0, 0); period];
+    for (index, sample) in samples.iter().enumerate() {
+        let (sum, count) = &mut sums[index % period];
+        *sum += sample;
+        *count += 1;
+    }
+    let means = sums
+        .iter()
+        .filter(|(_, count)| *count > 0)
+        .map(|(sum, count)| sum / *count as f64)
+        .collect::<Vec<_>>();
+    let max = means.iter().cloned().fold(std::f64::MIN, f64::max);
+    let min = means.iter().cloned().fold(std::f64::MAX, f64::min);
+    (max - min) / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One burst of 10 transactions every 5 blocks, for 100 blocks
+    fn bursts() -> Vec<f64> {
+        (0..100)
+            .map(|index| if index % 5 == 0 { 10.0 } else { 0.0 })
+            .collect()
+    }
+
+    #[test]
+    fn test_dominant_period_of_bursts() {
+        let (period, correlation) = dominant_period(&bursts()).expect("periodic counts");
+        assert_eq!(period, 5);
+        assert!((correlation - 0.95).abs() < 1e-9);
+        assert!((phase_amplitude(&bursts(), period) - 5.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_dominant_period_of_constant_counts() {
+        assert_eq!(dominant_period(&[3.0; 100]), None);
+    }
+
+    #[test]
+    fn test_dominant_period_of_short_counts() {
+        assert_eq!(dominant_period(&[10.0, 0.0, 10.0]), None);
+    }
+}
@@ -0,0 +1,292 @@
+#![allow(clippy::mutable_key_type)]
+use crate::global::BLOCK_PROPAGATION;
+use crate::net::Net;
+use crate::statistics::Percentiles;
+
+use ckb_types::core::{BlockNumber, HeaderView};
+use ckb_types::packed::Byte32;
+use crossbeam_channel::{bounded, Sender, TryRecvError};
+use log::warn;
+use rand::Rng;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread::{sleep, spawn};
+use std::time::{Duration, Instant};
+
+const TIP_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The first time each block is observed as the tip of each endpoint.
+///
+/// A block which is overtaken before being polled is never observed by the endpoint, so only the
+/// blocks observed by an endpoint count into its delays.
+#[derive(Default)]
+pub struct BlockPropagation {
+    endpoints: usize,
+    first_seen: HashMap<Byte32, (BlockNumber, Vec<Option<Instant>>)>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct BlockPropagationMetrics {
+    endpoint: String,
+    observed_blocks: u64,
+    /// From the block being observed on the first endpoint to on this endpoint
+    delay_ms: Percentiles,
+}
+
+impl BlockPropagation {
+    pub fn reset(&mut self, endpoints: usize) {
+        self.endpoints = endpoints;
+        self.first_seen.clear();
+    }
+
+    pub fn record(&mut self, endpoint: usize, header: &HeaderView, seen_at: Instant) {
+        let endpoints = self.endpoints;
+        let (_, seen) = self
+            .first_seen
+            .entry(header.hash())
+            .or_insert_with(|| (header.number(), vec![None; endpoints]));
+        if seen[endpoint].is_none() {
+            seen[endpoint] = Some(seen_at);
+        }
+    }
+
+    /// Evaluate the propagation delays on each endpoint of the blocks in
+    /// `[from_number, end_number]`. Return empty if no block is monitored.
+    pub fn metrics(
+        &self,
+        net: &Net,
+        from_number: BlockNumber,
+        end_number: BlockNumber,
+    ) -> Vec<BlockPropagationMetrics> {
+        if self.first_seen.is_empty() {
+            return Vec::new();
+        }
+
+        let mut delays = vec![Vec::new(); self.endpoints];
+        for (number, seen) in self.first_seen.values() {
+            if *number < from_number || *number > end_number {
+                continue;
+            }
+            let earliest = seen
+                .iter()
+                .flatten()
+                .min()
+                .expect("seen by at least one endpoint");
+            for (endpoint, seen_at) in seen.iter().enumerate() {
+                if let Some(seen_at) = seen_at {
+                    delays[endpoint].push((*seen_at - *earliest).as_millis() as u64);
+                }
+            }
+        }
+        net.endpoints()
+            .iter()
+            .zip(delays.iter())
+            .map(|(rpc, delays)| BlockPropagationMetrics {
+                endpoint: rpc.uri().clone(),
+                observed_blocks: delays.len() as u64,
+                delay_ms: Percentiles::new(delays),
+            })
+            .collect()
+    }
+}
+
+/// Poll `get_tip_header` on every endpoint and record when each block first appears on each
+/// node into the global `BLOCK_PROPAGATION`, until the returned sender is dropped.
+pub fn spawn_block_propagation_monitor(net: &Net) -> Sender<()> {
+    BLOCK_PROPAGATION
+        .lock()
+        .unwrap()
+        .reset(net.endpoints().len());
+
+    let (stopper, stop_receiver) = bounded::<()>(0);
+    for (endpoint, rpc) in net.endpoints().iter().enumerate() {
+        let rpc = rpc.clone();
+        let stop_receiver = stop_receiver.clone();
+        spawn(move || {
+            let mut last_tip_hash = None;
+            loop {
+                if let Err(TryRecvError::Disconnected) = stop_receiver.try_recv() {
+                    return;
+                }
+
+                let tip_header: HeaderView = match rpc.get_tip_header() {
+                    Ok(tip_header) => tip_header.into(),
+                    Err(err) => {
+                        warn!("propagation get_tip_header({}) error: {}", rpc.uri(), err);
+                        sleep(TIP_POLL_INTERVAL);
+                        continue;
+                    }
+                };
+                let seen_at = Instant::now();
+                if last_tip_hash.as_ref() != Some(&tip_header.hash()) {
+                    BLOCK_PROPAGATION
+                        .lock()
+                        .unwrap()
+                        .record(endpoint, &tip_header, seen_at);
+                    last_tip_hash = Some(tip_header.hash());
+                }
+                sleep(TIP_POLL_INTERVAL);
+            }
+        });
+    }
+    stopper
+}
+
+const TRANSACTION_POLL_INTERVAL: Duration = Duration::from_millis(200);
+const TRANSACTION_PROPAGATION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Tracks how long a random subset of the transactions, by `sample_rate`, takes to reach the
+/// other endpoints after being accepted by the one they were sent to.
+///
+/// A transaction not seen on an endpoint within `TRANSACTION_PROPAGATION_TIMEOUT` is counted as
+/// never arrived.
+#[derive(Clone, Default)]
+pub struct TransactionPropagationTracker {
+    inner: Arc<Mutex<TransactionPropagationTrackerInner>>,
+}
+
+#[derive(Default)]
+struct TransactionPropagationTrackerInner {
+    sample_rate: f64,
+    endpoints: usize,
+    transactions: HashMap<Byte32, TransactionPropagation>,
+}
+
+struct TransactionPropagation {
+    endpoint: usize,
+    accepted_at: Instant,
+    seen_at: Vec<Option<Instant>>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct TransactionPropagationMetrics {
+    sampled_transactions: u64,
+    /// The (transaction, other endpoint) pairs which arrived
+    arrived: u64,
+    /// The (transaction, other endpoint) pairs which never arrived within the timeout
+    never_arrived: u64,
+    never_arrived_rate: f64,
+    /// From being accepted by the sent endpoint to being seen on another endpoint
+    delay_ms: Percentiles,
+}
+
+impl TransactionPropagationTracker {
+    pub fn new(sample_rate: f64, endpoints: usize) -> Self {
+        let tracker = Self::default();
+        {
+            let mut inner = tracker.inner.lock().unwrap();
+            inner.sample_rate = sample_rate.max(0.0).min(1.0);
+            inner.endpoints = endpoints;
+        }
+        tracker
+    }
+
+    pub fn record_accepted(&self, tx_hash: Byte32, endpoint: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.endpoints < 2
+            || inner.sample_rate <= 0.0
+            || !rand::thread_rng().gen_bool(inner.sample_rate)
+        {
+            return;
+        }
+
+        let mut seen_at = vec![None; inner.endpoints];
+        let accepted_at = Instant::now();
+        seen_at[endpoint] = Some(accepted_at);
+        inner.transactions.insert(
+            tx_hash,
+            TransactionPropagation {
+                endpoint,
+                accepted_at,
+                seen_at,
+            },
+        );
+    }
+
+    fn record_seen(&self, tx_hash: &Byte32, endpoint: usize, seen_at: Instant) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(propagation) = inner.transactions.get_mut(tx_hash) {
+            if propagation.seen_at[endpoint].is_none() {
+                propagation.seen_at[endpoint] = Some(seen_at);
+            }
+        }
+    }
+
+    // Return the sampled transactions and the endpoints which have not seen them yet, in the
+    // timeout.
+    fn observing_transactions(&self) -> Vec<(Byte32, usize)> {
+        let inner = self.inner.lock().unwrap();
+        let mut observing = Vec::new();
+        for (tx_hash, propagation) in inner.transactions.iter() {
+            if propagation.accepted_at.elapsed() >= TRANSACTION_PROPAGATION_TIMEOUT {
+                continue;
+            }
+            for (endpoint, seen_at) in propagation.seen_at.iter().enumerate() {
+                if seen_at.is_none() {
+                    observing.push((tx_hash.clone(), endpoint));
+                }
+            }
+        }
+        observing
+    }
+
+    pub fn metrics(&self) -> TransactionPropagationMetrics {
+        let inner = self.inner.lock().unwrap();
+        let (mut delays, mut never_arrived) = (Vec::new(), 0);
+        for propagation in inner.transactions.values() {
+            let timed_out = propagation.accepted_at.elapsed() >= TRANSACTION_PROPAGATION_TIMEOUT;
+            for (endpoint, seen_at) in propagation.seen_at.iter().enumerate() {
+                if endpoint == propagation.endpoint {
+                    continue;
+                }
+                match seen_at {
+                    Some(seen_at) => delays.push(
+                        seen_at
+                            .saturating_duration_since(propagation.accepted_at)
+                            .as_millis() as u64,
+                    ),
+                    // Still on the way if not timed out yet
+                    None if timed_out => never_arrived += 1,
+                    None => {}
+                }
+            }
+        }
+        let pairs = delays.len() as u64 + never_arrived;
+        TransactionPropagationMetrics {
+            sampled_transactions: inner.transactions.len() as u64,
+            arrived: delays.len() as u64,
+            never_arrived,
+            never_arrived_rate: if pairs == 0 {
+                0.0
+            } else {
+                never_arrived as f64 / pairs as f64
+            },
+            delay_ms: Percentiles::new(&delays),
+        }
+    }
+}
+
+/// Poll `get_transaction` of the sampled transactions on the endpoints which have not seen them,
+/// and record the first time they are seen into `tracker`, until the returned sender is dropped.
+pub fn spawn_transaction_propagation_sampler(
+    net: &Net,
+    tracker: TransactionPropagationTracker,
+) -> Sender<()> {
+    let endpoints = net.endpoints().clone();
+    let (stopper, stop_receiver) = bounded::<()>(0);
+    spawn(move || loop {
+        if let Err(TryRecvError::Disconnected) = stop_receiver.try_recv() {
+            return;
+        }
+
+        for (tx_hash, endpoint) in tracker.observing_transactions() {
+            // An error is taken as not seen yet, and polled again next round
+            if let Ok(Some(_)) = endpoints[endpoint].get_transaction(tx_hash.clone()) {
+                tracker.record_seen(&tx_hash, endpoint, Instant::now());
+            }
+        }
+        sleep(TRANSACTION_POLL_INTERVAL);
+    });
+    stopper
+}
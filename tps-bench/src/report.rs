@@ -0,0 +1,532 @@
+use crate::config::Config;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+use std::str::FromStr;
+
+const CONFIGURATION_MARKER: &str = "TPSBench start with configuration: ";
+const RUN_ID_MARKER: &str = "[RUN ID] ";
+const NODE_VERSIONS_MARKER: &str = "[NODE VERSIONS] ";
+const FIND_BEST_TPS_STEP_MARKER: &str = "[FIND BEST TPS STEP] ";
+const BEST_TPS_MARKER: &str = "Best TPS: ";
+
+const CHART_WIDTH: f64 = 720.0;
+const CHART_HEIGHT: f64 = 240.0;
+const CHART_MARGIN: f64 = 48.0;
+const CHART_COLORS: &[&str] = &[
+    "#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd", "#8c564b",
+];
+
+#[derive(Debug, Clone, Copy)]
+pub enum ReportFormat {
+    Markdown,
+    Html,
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "markdown" => Ok(ReportFormat::Markdown),
+            "html" => Ok(ReportFormat::Html),
+            _ => Err(format!("unsupported report format: {}", s)),
+        }
+    }
+}
+
+/// The data of a run collected from its data directory. `bench.log` may accumulate several runs,
+/// only the last one is taken from it.
+#[derive(Default)]
+struct RunData {
+    configuration: Option<Value>,
+    /// `None` for the runs before the run id was recorded
+    run_id: Option<String>,
+    node_versions: Option<Value>,
+    find_best_tps_steps: Vec<Value>,
+    best_tps: Option<String>,
+    results: Vec<Value>,
+    statistics: Vec<Value>,
+    txpool_samples: Vec<Value>,
+}
+
+// A format-neutral report, rendered into Markdown or HTML
+enum Block {
+    Heading(String),
+    Paragraph(String),
+    Code(String),
+    Table(Vec<String>, Vec<Vec<String>>),
+    Svg(String),
+}
+
+/// Render a self-contained report of the run in `data_dir`.
+pub fn render_report(data_dir: &Path, format: ReportFormat) -> String {
+    let data = RunData::load(data_dir);
+    let blocks = report_blocks(&data);
+    match format {
+        ReportFormat::Markdown => render_markdown(&blocks),
+        ReportFormat::Html => render_html(&blocks),
+    }
+}
+
+impl RunData {
+    fn load(data_dir: &Path) -> Self {
+        let mut data = RunData::default();
+        if let Some(log) = read_optional(&data_dir.join("bench.log")) {
+            for line in log.lines() {
+                if let Some(configuration) = parse_marked(line, CONFIGURATION_MARKER) {
+                    // A new run starts
+                    data = RunData {
+                        configuration: redact_configuration(configuration),
+                        ..Default::default()
+                    };
+                } else if let Some(index) = line.find(RUN_ID_MARKER) {
+                    data.run_id = Some(line[index + RUN_ID_MARKER.len()..].trim().to_owned());
+                } else if let Some(node_versions) = parse_marked(line, NODE_VERSIONS_MARKER) {
+                    data.node_versions = Some(node_versions);
+                } else if let Some(step) = parse_marked(line, FIND_BEST_TPS_STEP_MARKER) {
+                    data.find_best_tps_steps.push(step);
+                } else if let Some(index) = line.find(BEST_TPS_MARKER) {
+                    data.best_tps = Some(line[index + BEST_TPS_MARKER.len()..].trim().to_owned());
+                }
+            }
+        }
+        // Only the lines of the last run, the data files are appended by every run
+        let run_id = data.run_id.clone();
+        let is_last_run =
+            |line: &Value| line.get("run_id").and_then(Value::as_str) == run_id.as_deref();
+        for line in read_json_lines(&data_dir.join("metrics.json")) {
+            if !is_last_run(&line) {
+                continue;
+            }
+            if line.get("statistics").is_some() {
+                data.statistics.push(line);
+            } else if line.get("metrics").is_some() {
+                data.results.push(line);
+            }
+        }
+        data.txpool_samples = read_json_lines(&data_dir.join("txpool.jsonl"))
+            .into_iter()
+            .filter(|line| is_last_run(line))
+            .collect();
+
+        if data.configuration.is_none() && data.results.is_empty() {
+            prompt_and_exit!(
+                "neither bench.log nor metrics.json found in {}",
+                data_dir.to_string_lossy()
+            );
+        }
+        data
+    }
+}
+
+fn read_optional(path: &Path) -> Option<String> {
+    if !path.exists() {
+        return None;
+    }
+    let content = std::fs::read_to_string(path).unwrap_or_else(|err| {
+        prompt_and_exit!("read {} error: {}", path.to_string_lossy(), err);
+    });
+    Some(content)
+}
+
+// Skip the unparsable lines, e.g. the last line truncated by an interrupted run
+fn read_json_lines(path: &Path) -> Vec<Value> {
+    read_optional(path)
+        .map(|content| {
+            content
+                .lines()
+                .filter_map(|line| serde_json::from_str(line).ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Redact the logged configuration via `Config::redacted`, in case the log predates it, as the
+/// report is meant to be shared. It is left out if it does not parse as a `Config`.
+fn redact_configuration(value: Value) -> Option<Value> {
+    let config: Config = serde_json::from_value(value).ok()?;
+    Some(json!(config.redacted()))
+}
+
+fn parse_marked(line: &str, marker: &str) -> Option<Value> {
+    line.find(marker)
+        .and_then(|index| serde_json::from_str(&line[index + marker.len()..]).ok())
+}
+
+fn report_blocks(data: &RunData) -> Vec<Block> {
+    let mut blocks = vec![Block::Heading("TPS Benchmark Report".to_owned())];
+
+    blocks.push(Block::Heading("Configuration".to_owned()));
+    match &data.configuration {
+        Some(configuration) => blocks.push(Block::Code(
+            serde_json::to_string_pretty(configuration).expect("serialize json value"),
+        )),
+        None => blocks.push(Block::Paragraph(
+            "Not found in bench.log, or not in the format of this version.".to_owned(),
+        )),
+    }
+
+    blocks.push(Block::Heading("Node Versions".to_owned()));
+    match data.node_versions.as_ref().and_then(Value::as_array) {
+        Some(node_versions) => {
+            let rows = node_versions
+                .iter()
+                .map(|node| vec![field(node, "/endpoint"), field(node, "/version")])
+                .collect();
+            blocks.push(Block::Table(
+                vec!["endpoint".to_owned(), "version".to_owned()],
+                rows,
+            ));
+        }
+        None => blocks.push(Block::Paragraph("Not found in bench.log.".to_owned())),
+    }
+
+    blocks.push(Block::Heading("Benchmarks".to_owned()));
+    if data.results.is_empty() {
+        blocks.push(Block::Paragraph("No result in metrics.json.".to_owned()));
+    } else {
+        let columns = [
+            ("send_delay", "/benchmark/send_delay"),
+            ("transaction_type", "/benchmark/transaction_type"),
+            ("repetition", "/repetition"),
+            ("tps", "/metrics/tps"),
+            ("average_block_time_ms", "/metrics/average_block_time_ms"),
+            ("uncle_rate", "/metrics/uncle_rate"),
+            ("latency_p50_ms", "/latency/confirmation_latency_ms/p50"),
+            ("latency_p90_ms", "/latency/confirmation_latency_ms/p90"),
+            ("latency_p99_ms", "/latency/confirmation_latency_ms/p99"),
+            ("client_bottleneck", "/client/client_bottleneck"),
+        ];
+        blocks.push(table(&columns, &data.results));
+    }
+    if !data.statistics.is_empty() {
+        let columns = [
+            ("send_delay", "/benchmark/send_delay"),
+            ("transaction_type", "/benchmark/transaction_type"),
+            ("repetitions", "/statistics/repetitions/count"),
+            ("mean_tps", "/statistics/repetitions/mean"),
+            (
+                "standard_deviation",
+                "/statistics/repetitions/standard_deviation",
+            ),
+            (
+                "coefficient_of_variation",
+                "/statistics/repetitions/coefficient_of_variation",
+            ),
+            ("stable", "/statistics/stable"),
+        ];
+        blocks.push(table(&columns, &data.statistics));
+    }
+
+    blocks.push(Block::Heading("TPS Over Time".to_owned()));
+    let tps_series = data
+        .results
+        .iter()
+        .flat_map(
+            |result| match result.get("sub_window_tps").and_then(Value::as_array) {
+                Some(sub_window_tps) if !sub_window_tps.is_empty() => sub_window_tps.clone(),
+                _ => result
+                    .pointer("/metrics/tps")
+                    .cloned()
+                    .into_iter()
+                    .collect(),
+            },
+        )
+        .filter_map(|tps| tps.as_f64())
+        .enumerate()
+        .map(|(index, tps)| (index as f64, tps))
+        .collect::<Vec<_>>();
+    if tps_series.is_empty() {
+        blocks.push(Block::Paragraph("No result in metrics.json.".to_owned()));
+    } else {
+        blocks.push(Block::Paragraph(
+            "The TPS of every sub-window, in the order the benchmarks ran.".to_owned(),
+        ));
+        blocks.push(Block::Svg(line_chart(
+            "sub-window",
+            "TPS",
+            &[("tps".to_owned(), tps_series)],
+        )));
+    }
+
+    blocks.push(Block::Heading("Tx Pool Size".to_owned()));
+    let pool_series = pool_size_series(&data.txpool_samples);
+    if pool_series.is_empty() {
+        blocks.push(Block::Paragraph("No sample in txpool.jsonl.".to_owned()));
+    } else {
+        blocks.push(Block::Paragraph(
+            "The pending and proposed transactions of every endpoint.".to_owned(),
+        ));
+        blocks.push(Block::Svg(line_chart(
+            "seconds",
+            "transactions",
+            &pool_series,
+        )));
+    }
+
+    blocks.push(Block::Heading("Find Best TPS".to_owned()));
+    if data.find_best_tps_steps.is_empty() {
+        blocks.push(Block::Paragraph(
+            "Not found in bench.log, skipped or not finished.".to_owned(),
+        ));
+    } else {
+        let columns = [("send_delay", "/send_delay"), ("tps", "/tps")];
+        let mut steps = table(&columns, &data.find_best_tps_steps);
+        if let Block::Table(headers, rows) = &mut steps {
+            headers.insert(0, "step".to_owned());
+            for (index, row) in rows.iter_mut().enumerate() {
+                row.insert(0, (index + 1).to_string());
+            }
+        }
+        blocks.push(steps);
+    }
+    if let Some(best_tps) = &data.best_tps {
+        blocks.push(Block::Paragraph(format!("Best TPS: {}", best_tps)));
+    }
+    blocks
+}
+
+fn field(value: &Value, pointer: &str) -> String {
+    match value.pointer(pointer) {
+        Some(Value::String(string)) => string.clone(),
+        Some(Value::Null) | None => String::new(),
+        Some(Value::Number(number)) => match number.as_f64() {
+            Some(float) if number.is_f64() => format!("{:.4}", float),
+            _ => number.to_string(),
+        },
+        Some(other) => other.to_string(),
+    }
+}
+
+fn table(columns: &[(&str, &str)], values: &[Value]) -> Block {
+    let headers = columns.iter().map(|(name, _)| (*name).to_owned()).collect();
+    let rows = values
+        .iter()
+        .map(|value| {
+            columns
+                .iter()
+                .map(|(_, pointer)| field(value, pointer))
+                .collect()
+        })
+        .collect();
+    Block::Table(headers, rows)
+}
+
+// pending + proposed of every endpoint, in seconds since the first sample
+fn pool_size_series(samples: &[Value]) -> Vec<(String, Vec<(f64, f64)>)> {
+    let start = match samples
+        .iter()
+        .filter_map(|sample| sample.get("timestamp").and_then(Value::as_u64))
+        .min()
+    {
+        Some(start) => start,
+        None => return Vec::new(),
+    };
+    let mut series: BTreeMap<String, Vec<(f64, f64)>> = BTreeMap::new();
+    for sample in samples {
+        let timestamp = sample.get("timestamp").and_then(Value::as_u64);
+        let endpoint = sample.get("endpoint").and_then(Value::as_str);
+        if let (Some(timestamp), Some(endpoint)) = (timestamp, endpoint) {
+            let pending = sample.get("pending").and_then(Value::as_u64).unwrap_or(0);
+            let proposed = sample.get("proposed").and_then(Value::as_u64).unwrap_or(0);
+            series.entry(endpoint.to_owned()).or_default().push((
+                (timestamp - start) as f64 / 1000.0,
+                (pending + proposed) as f64,
+            ));
+        }
+    }
+    series.into_iter().collect()
+}
+
+// An inline SVG line chart of `series`, each of which is a name and its (x, y) points
+fn line_chart(x_label: &str, y_label: &str, series: &[(String, Vec<(f64, f64)>)]) -> String {
+    let points = series.iter().flat_map(|(_, points)| points.iter());
+    let (mut x_max, mut y_max) = (0.0f64, 0.0f64);
+    let mut x_min = f64::MAX;
+    for (x, y) in points {
+        x_min = x_min.min(*x);
+        x_max = x_max.max(*x);
+        y_max = y_max.max(*y);
+    }
+    let x_span = if x_max > x_min { x_max - x_min } else { 1.0 };
+    let y_span = if y_max > 0.0 { y_max } else { 1.0 };
+    let plot_width = CHART_WIDTH - 2.0 * CHART_MARGIN;
+    let plot_height = CHART_HEIGHT - 2.0 * CHART_MARGIN;
+    let scale = |(x, y): &(f64, f64)| {
+        (
+            CHART_MARGIN + (x - x_min) / x_span * plot_width,
+            CHART_MARGIN + plot_height - y / y_span * plot_height,
+        )
+    };
+
+    let mut svg = String::new();
+    let _ = write!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-family="sans-serif" font-size="11">"#,
+        w = CHART_WIDTH,
+        h = CHART_HEIGHT,
+    );
+    let (left, top) = (CHART_MARGIN, CHART_MARGIN);
+    let (right, bottom) = (CHART_MARGIN + plot_width, CHART_MARGIN + plot_height);
+    let _ = write!(
+        svg,
+        r##"<polyline fill="none" stroke="#888" points="{left},{top} {left},{bottom} {right},{bottom}"/>"##,
+        left = left,
+        top = top,
+        right = right,
+        bottom = bottom,
+    );
+    let _ = write!(
+        svg,
+        r#"<text x="{}" y="{}" text-anchor="end">{}</text><text x="{}" y="{}" text-anchor="end">0</text>"#,
+        left - 4.0,
+        top + 4.0,
+        y_max,
+        left - 4.0,
+        bottom,
+    );
+    let _ = write!(
+        svg,
+        r#"<text x="{}" y="{}">{}</text><text x="{}" y="{}" text-anchor="end">{}</text>"#,
+        left,
+        bottom + 14.0,
+        x_min,
+        right,
+        bottom + 14.0,
+        x_max,
+    );
+    let _ = write!(
+        svg,
+        r#"<text x="{}" y="{}" text-anchor="middle">{}</text><text x="{}" y="{}">{}</text>"#,
+        left + plot_width / 2.0,
+        bottom + 30.0,
+        escape_html(x_label),
+        left,
+        top - 8.0,
+        escape_html(y_label),
+    );
+    for (index, (name, points)) in series.iter().enumerate() {
+        let color = CHART_COLORS[index % CHART_COLORS.len()];
+        let polyline = points
+            .iter()
+            .map(|point| {
+                let (x, y) = scale(point);
+                format!("{:.1},{:.1}", x, y)
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        let _ = write!(
+            svg,
+            r#"<polyline fill="none" stroke="{}" stroke-width="1.5" points="{}"/>"#,
+            color, polyline,
+        );
+        let _ = write!(
+            svg,
+            r#"<text x="{}" y="{}" fill="{}" text-anchor="end">{}</text>"#,
+            right,
+            top - 8.0 + 12.0 * index as f64,
+            color,
+            escape_html(name),
+        );
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+fn render_markdown(blocks: &[Block]) -> String {
+    let mut markdown = String::new();
+    let mut headings = 0;
+    for block in blocks {
+        match block {
+            Block::Heading(heading) => {
+                let level = if headings == 0 { "#" } else { "##" };
+                headings += 1;
+                let _ = writeln!(markdown, "{} {}\n", level, heading);
+            }
+            Block::Paragraph(paragraph) => {
+                let _ = writeln!(markdown, "{}\n", paragraph);
+            }
+            Block::Code(code) => {
+                let _ = writeln!(markdown, "```json\n{}\n```\n", code);
+            }
+            Block::Table(headers, rows) => {
+                let cell = |cell: &String| cell.replace('|', "\\|");
+                let _ = writeln!(
+                    markdown,
+                    "| {} |",
+                    headers.iter().map(cell).collect::<Vec<_>>().join(" | ")
+                );
+                let _ = writeln!(markdown, "|{}", " --- |".repeat(headers.len()));
+                for row in rows {
+                    let _ = writeln!(
+                        markdown,
+                        "| {} |",
+                        row.iter().map(cell).collect::<Vec<_>>().join(" | ")
+                    );
+                }
+                markdown.push('\n');
+            }
+            // Markdown renderers like GitHub's accept inline HTML
+            Block::Svg(svg) => {
+                let _ = writeln!(markdown, "{}\n", svg);
+            }
+        }
+    }
+    markdown
+}
+
+fn render_html(blocks: &[Block]) -> String {
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>TPS Benchmark Report</title>\n<style>\n\
+         body { font-family: sans-serif; margin: 2em; }\n\
+         table { border-collapse: collapse; }\n\
+         th, td { border: 1px solid #ccc; padding: 4px 8px; text-align: right; }\n\
+         pre { background: #f6f8fa; padding: 1em; overflow: auto; }\n\
+         </style>\n</head>\n<body>\n",
+    );
+    let mut headings = 0;
+    for block in blocks {
+        match block {
+            Block::Heading(heading) => {
+                let level = if headings == 0 { 1 } else { 2 };
+                headings += 1;
+                let _ = writeln!(html, "<h{0}>{1}</h{0}>", level, escape_html(heading));
+            }
+            Block::Paragraph(paragraph) => {
+                let _ = writeln!(html, "<p>{}</p>", escape_html(paragraph));
+            }
+            Block::Code(code) => {
+                let _ = writeln!(html, "<pre><code>{}</code></pre>", escape_html(code));
+            }
+            Block::Table(headers, rows) => {
+                html.push_str("<table>\n<tr>");
+                for header in headers {
+                    let _ = write!(html, "<th>{}</th>", escape_html(header));
+                }
+                html.push_str("</tr>\n");
+                for row in rows {
+                    html.push_str("<tr>");
+                    for cell in row {
+                        let _ = write!(html, "<td>{}</td>", escape_html(cell));
+                    }
+                    html.push_str("</tr>\n");
+                }
+                html.push_str("</table>\n");
+            }
+            Block::Svg(svg) => {
+                let _ = writeln!(html, "<div>{}</div>", svg);
+            }
+        }
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
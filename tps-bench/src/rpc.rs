@@ -1,7 +1,10 @@
+use crate::config::Url;
+use crate::global::{RPC_AUTH, RPC_RETRY_POLICY, RPC_RUNTIME};
+use crate::send_error::SendError;
 use ckb_jsonrpc_types::{
     Block, BlockNumber, BlockTemplate, BlockView, CellOutputWithOutPoint, CellWithStatus,
-    ChainInfo, DryRunResult, HeaderView, LocalNode, OutPoint, PeerState, RemoteNode, Transaction,
-    TransactionWithStatus, TxPoolInfo, Uint64, Version,
+    ChainInfo, HeaderView, LocalNode, OutPoint, RemoteNode, Transaction, TransactionWithStatus,
+    TxPoolInfo, Uint64, Version,
 };
 use ckb_types::{
     core::{BlockNumber as CoreBlockNumber, Version as CoreVersion},
@@ -9,17 +12,115 @@ use ckb_types::{
     prelude::*,
     H256,
 };
-use ckb_util::Mutex;
-use hyper::header::{Authorization, Basic};
-use jsonrpc_client_core::{expand_params, jsonrpc_client, Result as JsonRpcResult};
-use jsonrpc_client_http::{HttpHandle, HttpTransport};
+use crossbeam_channel::bounded;
+use log::warn;
+use metrics::{counter, timing};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::de::DeserializeOwned;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
 use std::env::var;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use url::percent_encoding::percent_decode;
 
+/// The most requests sent in one JSON-RPC batch.
+pub const RPC_BATCH_SIZE: usize = 100;
+
+/// A JSON-RPC client of a ckb endpoint.
+///
+/// The requests are executed on the shared `RPC_RUNTIME`, over a pool of connections per
+/// endpoint, so the calls from different threads are in flight concurrently rather than queueing
+/// behind each other. Clones share the connection pool.
 #[derive(Clone)]
 pub struct Jsonrpc {
+    // Without the credentials, so that it is safe to log and label the metrics with
     uri: String,
-    inner: Arc<Mutex<Inner<HttpHandle>>>,
+    client: reqwest::Client,
+    next_id: Arc<AtomicU64>,
+    basic_auth: Option<(String, String)>,
+    bearer_token: Option<String>,
+}
+
+/// The credentials of an endpoint, besides the `username:password` embedded in its URL.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct EndpointAuth {
+    /// The endpoint, matched against the `--rpc-urls` regardless of their credentials
+    pub url: Url,
+    /// Sent as `Authorization: Bearer <bearer_token>`, instead of the basic auth
+    #[serde(default)]
+    pub bearer_token: Option<String>,
+    /// The extra HTTP headers sent along with every request, e.g. API keys of RPC gateways
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum RpcError {
+    /// The request did not get a JSON-RPC response
+    Transport(String),
+    /// The HTTP error status without a JSON-RPC response, e.g. 401 for bad credentials
+    Status { status: u16, message: String },
+    /// The JSON-RPC error object in the response
+    Response { code: i64, message: String },
+    /// The response does not match the JSON-RPC protocol or the expected result type
+    Decode(String),
+    /// The expected block or header is not found, e.g. after a chain reorg
+    NotFound(String),
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RpcError::Transport(message) => write!(f, "transport error: {}", message),
+            RpcError::Status { status, message } => {
+                write!(f, "HTTP status {}: {}", status, message)
+            }
+            RpcError::Response { code, message } => {
+                write!(f, "JSON-RPC error {}: {}", code, message)
+            }
+            RpcError::Decode(message) => write!(f, "decode error: {}", message),
+            RpcError::NotFound(message) => write!(f, "not found: {}", message),
+        }
+    }
+}
+
+/// How the idempotent reads are retried after transport errors, with exponential backoff.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    /// The timeout of every request, so that a stuck connection fails and gets retried
+    pub timeout_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 5,
+            initial_backoff_ms: 200,
+            max_backoff_ms: 10_000,
+            timeout_ms: 60_000,
+        }
+    }
+}
+
+impl RpcError {
+    /// Whether the error may pass on retrying. A JSON-RPC error response is final, and so are the
+    /// HTTP client errors like bad credentials, except for timeouts and rate limiting.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            RpcError::Transport(_) => true,
+            RpcError::Status { status, .. } => *status == 408 || *status == 429 || *status >= 500,
+            RpcError::Response { .. } | RpcError::Decode(_) | RpcError::NotFound(_) => false,
+        }
+    }
 }
 
 pub fn username() -> String {
@@ -30,22 +131,70 @@ pub fn password() -> String {
     var("CKB_STAGING_PASSWORD").unwrap_or_else(|_| "".to_owned())
 }
 
+/// Return `url` without the `username:password`.
+fn without_credentials(url: &url::Url) -> url::Url {
+    let mut url = url.clone();
+    // Fails only for URLs which cannot carry credentials at all
+    let _ = url.set_username("");
+    let _ = url.set_password(None);
+    url
+}
+
 impl Jsonrpc {
+    /// Connect to `uri`. The `username:password` in `uri` is sent as basic auth, falling back to
+    /// the environment variables `CKB_STAGING_USERNAME` and `CKB_STAGING_PASSWORD`, and the
+    /// `rpc_auth` of the spec matching `uri` applies as well.
     pub fn connect(uri: &str) -> Self {
-        let transport = HttpTransport::new().standalone().unwrap();
-        let mut transport = transport
-            .handle(uri)
-            .unwrap_or_else(|_| panic!("Jsonrpc::connect({})", uri));
-
-        if !username().is_empty() && !password().is_empty() {
-            transport.set_header(Authorization(Basic {
-                username: username(),
-                password: Some(password()),
-            }));
+        let url =
+            url::Url::parse(uri).unwrap_or_else(|err| panic!("Jsonrpc::connect({}): {}", uri, err));
+        let basic_auth = if !url.username().is_empty() {
+            let decode = |s: &str| {
+                percent_decode(s.as_bytes())
+                    .decode_utf8_lossy()
+                    .into_owned()
+            };
+            Some((
+                decode(url.username()),
+                url.password().map(decode).unwrap_or_default(),
+            ))
+        } else if !username().is_empty() && !password().is_empty() {
+            Some((username(), password()))
+        } else {
+            None
+        };
+        let url = without_credentials(&url);
+        let auth = RPC_AUTH
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|auth| without_credentials(&auth.url) == url)
+            .cloned();
+
+        let mut headers = HeaderMap::new();
+        if let Some(auth) = auth.as_ref() {
+            for (name, value) in auth.headers.iter() {
+                let header_name = HeaderName::from_bytes(name.as_bytes()).unwrap_or_else(|err| {
+                    panic!("Jsonrpc::connect({}) header {}: {}", url, name, err)
+                });
+                let mut header_value = HeaderValue::from_str(value).unwrap_or_else(|err| {
+                    panic!("Jsonrpc::connect({}) header {}: {}", url, name, err)
+                });
+                header_value.set_sensitive(true);
+                headers.insert(header_name, header_value);
+            }
         }
+        let timeout = Duration::from_millis(RPC_RETRY_POLICY.lock().unwrap().timeout_ms);
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .default_headers(headers)
+            .build()
+            .unwrap_or_else(|err| panic!("Jsonrpc::connect({}): {}", url, err));
         Self {
-            uri: uri.to_string(),
-            inner: Arc::new(Mutex::new(Inner::new(transport))),
+            uri: url.into_string(),
+            client,
+            next_id: Arc::new(AtomicU64::new(0)),
+            basic_auth,
+            bearer_token: auth.and_then(|auth| auth.bearer_token),
         }
     }
 
@@ -53,56 +202,206 @@ impl Jsonrpc {
         &self.uri
     }
 
-    pub fn inner(&self) -> &Mutex<Inner<HttpHandle>> {
-        &self.inner
+    // Call `method` with the positional `params` and record its latency
+    fn call<T: DeserializeOwned>(
+        &self,
+        method: &'static str,
+        params: Value,
+    ) -> Result<T, RpcError> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params,
+        });
+        let start = Instant::now();
+        let result = self.post(request).and_then(decode_response);
+        timing!(
+            "tps_bench.rpc.latency",
+            start,
+            Instant::now(),
+            "method" => method,
+            "endpoint" => self.uri.clone()
+        );
+        result
     }
 
-    pub fn get_block(&self, hash: Byte32) -> Option<BlockView> {
-        self.inner
-            .lock()
-            .get_block(hash.unpack())
-            .call()
-            .unwrap_or_else(|_| panic!("Jsonrpc::get_block({}, {})", self.uri(), hash))
+    // Call `method` once per element of `params` in a JSON-RPC batch, and return the results in
+    // the order of `params`
+    fn batch<T: DeserializeOwned>(
+        &self,
+        method: &'static str,
+        params: Vec<Value>,
+    ) -> Result<Vec<T>, RpcError> {
+        if params.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let count = params.len() as u64;
+        let first_id = self.next_id.fetch_add(count, Ordering::Relaxed);
+        let requests = params
+            .into_iter()
+            .zip(first_id..)
+            .map(|(params, id)| {
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "method": method,
+                    "params": params,
+                })
+            })
+            .collect::<Vec<_>>();
+        let start = Instant::now();
+        let result = self.post(Value::Array(requests)).and_then(|responses| {
+            let responses = match responses {
+                Value::Array(responses) => responses,
+                other => return Err(RpcError::Decode(format!("not a batch response: {}", other))),
+            };
+            // The responses of a batch may come in any order
+            let mut responses = responses
+                .into_iter()
+                .filter_map(|response| {
+                    let id = response.get("id").and_then(Value::as_u64)?;
+                    Some((id, response))
+                })
+                .collect::<HashMap<_, _>>();
+            (first_id..first_id + count)
+                .map(|id| {
+                    let response = responses
+                        .remove(&id)
+                        .ok_or_else(|| RpcError::Decode(format!("missing response id {}", id)))?;
+                    decode_response(response)
+                })
+                .collect()
+        });
+        timing!(
+            "tps_bench.rpc.latency",
+            start,
+            Instant::now(),
+            "method" => method,
+            "endpoint" => self.uri.clone()
+        );
+        result
     }
 
-    pub fn get_block_by_number(&self, number: CoreBlockNumber) -> Option<BlockView> {
-        self.inner
-            .lock()
-            .get_block_by_number(number.into())
-            .call()
-            .unwrap_or_else(|_| panic!("Jsonrpc::get_block_by_number({}, {})", self.uri(), number))
+    // Call the idempotent `method`, retrying on transport errors by `RPC_RETRY_POLICY`
+    fn read<T: DeserializeOwned>(
+        &self,
+        method: &'static str,
+        params: Value,
+    ) -> Result<T, RpcError> {
+        self.retry(method, || self.call(method, params.clone()))
     }
 
-    pub fn get_transaction(&self, hash: Byte32) -> Option<TransactionWithStatus> {
-        self.inner
-            .lock()
-            .get_transaction(hash.unpack())
-            .call()
-            .unwrap_or_else(|_| panic!("Jsonrpc::get_transaction({}, {})", self.uri(), hash))
+    fn retry<T>(
+        &self,
+        method: &'static str,
+        call: impl Fn() -> Result<T, RpcError>,
+    ) -> Result<T, RpcError> {
+        let policy = *RPC_RETRY_POLICY.lock().unwrap();
+        let mut backoff = Duration::from_millis(policy.initial_backoff_ms);
+        let mut retries = 0;
+        loop {
+            match call() {
+                Err(err) if err.is_transient() && retries < policy.max_retries => {
+                    retries += 1;
+                    warn!(
+                        "Jsonrpc::{}({}) error: {}, retry {}/{} in {:?}",
+                        method,
+                        self.uri(),
+                        err,
+                        retries,
+                        policy.max_retries,
+                        backoff
+                    );
+                    counter!(
+                        "tps_bench.rpc.retries",
+                        1,
+                        "method" => method,
+                        "endpoint" => self.uri.clone()
+                    );
+                    sleep(backoff);
+                    backoff = (backoff * 2).min(Duration::from_millis(policy.max_backoff_ms));
+                }
+                result => return result,
+            }
+        }
     }
 
-    pub fn get_block_hash(&self, number: CoreBlockNumber) -> Option<H256> {
-        self.inner
-            .lock()
-            .get_block_hash(number.into())
-            .call()
-            .unwrap_or_else(|_| panic!("Jsonrpc::get_block_hash({}, {})", self.uri(), number))
+    // Post the request on `RPC_RUNTIME` and wait for the response
+    fn post(&self, body: Value) -> Result<Value, RpcError> {
+        let mut request = self.client.post(&self.uri).json(&body);
+        if let Some(bearer_token) = self.bearer_token.as_ref() {
+            request = request.bearer_auth(bearer_token);
+        } else if let Some((username, password)) = self.basic_auth.as_ref() {
+            request = request.basic_auth(username, Some(password));
+        }
+
+        let (sender, receiver) = bounded(1);
+        RPC_RUNTIME.spawn(async move {
+            let response = execute(request).await;
+            let _ = sender.send(response);
+        });
+        receiver
+            .recv()
+            .unwrap_or_else(|_| Err(RpcError::Transport("request dropped".to_owned())))
     }
 
-    pub fn get_tip_header(&self) -> HeaderView {
-        self.inner
-            .lock()
-            .get_tip_header()
-            .call()
-            .unwrap_or_else(|_| panic!("Jsonrpc::get_tip_header({})", self.uri()))
+    pub fn get_block(&self, hash: Byte32) -> Result<Option<BlockView>, RpcError> {
+        let hash: H256 = hash.unpack();
+        self.read("get_block", json!([hash]))
     }
 
-    pub fn get_header_by_number(&self, number: CoreBlockNumber) -> Option<HeaderView> {
-        self.inner
-            .lock()
-            .get_header_by_number(number.into())
-            .call()
-            .unwrap_or_else(|_| panic!("Jsonrpc::get_header_by_number({}, {})", self.uri(), number))
+    pub fn get_block_by_number(
+        &self,
+        number: CoreBlockNumber,
+    ) -> Result<Option<BlockView>, RpcError> {
+        self.read("get_block_by_number", json!([BlockNumber::from(number)]))
+    }
+
+    /// Get the blocks of `numbers` via JSON-RPC batches of at most `RPC_BATCH_SIZE`, in order.
+    pub fn get_blocks_by_number(
+        &self,
+        numbers: &[CoreBlockNumber],
+    ) -> Result<Vec<Option<BlockView>>, RpcError> {
+        let mut blocks = Vec::with_capacity(numbers.len());
+        for chunk in numbers.chunks(RPC_BATCH_SIZE) {
+            let params = chunk
+                .iter()
+                .map(|number| json!([BlockNumber::from(*number)]))
+                .collect::<Vec<_>>();
+            let chunk_blocks = self.retry("get_block_by_number", || {
+                self.batch("get_block_by_number", params.clone())
+            })?;
+            blocks.extend(chunk_blocks);
+        }
+        Ok(blocks)
+    }
+
+    pub fn get_transaction(&self, hash: Byte32) -> Result<Option<TransactionWithStatus>, RpcError> {
+        let hash: H256 = hash.unpack();
+        self.read("get_transaction", json!([hash]))
+    }
+
+    pub fn get_header(&self, hash: Byte32) -> Result<Option<HeaderView>, RpcError> {
+        let hash: H256 = hash.unpack();
+        self.read("get_header", json!([hash]))
+    }
+
+    pub fn get_block_hash(&self, number: CoreBlockNumber) -> Result<Option<H256>, RpcError> {
+        self.read("get_block_hash", json!([BlockNumber::from(number)]))
+    }
+
+    pub fn get_tip_header(&self) -> Result<HeaderView, RpcError> {
+        self.read("get_tip_header", json!([]))
+    }
+
+    pub fn get_header_by_number(
+        &self,
+        number: CoreBlockNumber,
+    ) -> Result<Option<HeaderView>, RpcError> {
+        self.read("get_header_by_number", json!([BlockNumber::from(number)]))
     }
 
     pub fn get_cells_by_lock_hash(
@@ -110,53 +409,29 @@ impl Jsonrpc {
         lock_hash: Byte32,
         from: CoreBlockNumber,
         to: CoreBlockNumber,
-    ) -> Vec<CellOutputWithOutPoint> {
-        self.inner
-            .lock()
-            .get_cells_by_lock_hash(lock_hash.unpack(), from.into(), to.into())
-            .call()
-            .unwrap_or_else(|_| {
-                panic!(
-                    "Jsonrpc::get_cells_by_lock_hash({}, {}, {}, {})",
-                    self.uri(),
-                    lock_hash,
-                    from,
-                    to
-                )
-            })
+    ) -> Result<Vec<CellOutputWithOutPoint>, RpcError> {
+        let lock_hash: H256 = lock_hash.unpack();
+        self.read(
+            "get_cells_by_lock_hash",
+            json!([lock_hash, BlockNumber::from(from), BlockNumber::from(to)]),
+        )
     }
 
-    pub fn get_live_cell(&self, out_point: OutPoint) -> CellWithStatus {
-        self.inner
-            .lock()
-            .get_live_cell(out_point.clone())
-            .call()
-            .unwrap_or_else(|_| panic!("Jsonrpc::get_live_cell({}, {:?})", self.uri(), out_point))
+    pub fn get_live_cell(&self, out_point: OutPoint) -> Result<CellWithStatus, RpcError> {
+        self.read("get_live_cell", json!([out_point]))
     }
 
-    pub fn get_tip_block_number(&self) -> CoreBlockNumber {
-        self.inner
-            .lock()
-            .get_tip_block_number()
-            .call()
-            .unwrap_or_else(|_| panic!("Jsonrpc::get_tip_block_number({})", self.uri()))
-            .into()
+    pub fn get_tip_block_number(&self) -> Result<CoreBlockNumber, RpcError> {
+        self.read::<BlockNumber>("get_tip_block_number", json!([]))
+            .map(Into::into)
     }
 
-    pub fn local_node_info(&self) -> LocalNode {
-        self.inner
-            .lock()
-            .local_node_info()
-            .call()
-            .unwrap_or_else(|_| panic!("Jsonrpc::local_node_info({})", self.uri()))
+    pub fn local_node_info(&self) -> Result<LocalNode, RpcError> {
+        self.read("local_node_info", json!([]))
     }
 
-    pub fn get_peers(&self) -> Vec<RemoteNode> {
-        self.inner
-            .lock()
-            .get_peers()
-            .call()
-            .unwrap_or_else(|_| panic!("Jsonrpc::get_peers({})", self.uri()))
+    pub fn get_peers(&self) -> Result<Vec<RemoteNode>, RpcError> {
+        self.read("get_peers", json!([]))
     }
 
     pub fn get_block_template(
@@ -164,140 +439,88 @@ impl Jsonrpc {
         bytes_limit: Option<u64>,
         proposals_limit: Option<u64>,
         max_version: Option<CoreVersion>,
-    ) -> BlockTemplate {
-        let bytes_limit = bytes_limit.map(Into::into);
-        let proposals_limit = proposals_limit.map(Into::into);
-        let max_version = max_version.map(Into::into);
-        self.inner
-            .lock()
-            .get_block_template(bytes_limit, proposals_limit, max_version)
-            .call()
-            .unwrap_or_else(|_| {
-                panic!(
-                    "Jsonrpc::get_block_template({}, {:?}, {:?}, {:?})",
-                    self.uri(),
-                    bytes_limit,
-                    proposals_limit,
-                    max_version
-                )
-            })
+    ) -> Result<BlockTemplate, RpcError> {
+        let bytes_limit: Option<Uint64> = bytes_limit.map(Into::into);
+        let proposals_limit: Option<Uint64> = proposals_limit.map(Into::into);
+        let max_version: Option<Version> = max_version.map(Into::into);
+        self.read(
+            "get_block_template",
+            json!([bytes_limit, proposals_limit, max_version]),
+        )
     }
 
-    pub fn submit_block(&self, work_id: String, block: Block) -> Option<H256> {
-        self.inner
-            .lock()
-            .submit_block(work_id, block.clone())
-            .call()
-            .unwrap_or_else(|_| panic!("Jsonrpc::submit_block({}, {:?})", self.uri(), block))
+    pub fn submit_block(&self, work_id: String, block: Block) -> Result<Option<H256>, RpcError> {
+        self.call("submit_block", json!([work_id, block]))
     }
 
-    pub fn get_blockchain_info(&self) -> ChainInfo {
-        self.inner
-            .lock()
-            .get_blockchain_info()
-            .call()
-            .unwrap_or_else(|_| panic!("Jsonrpc::get_blockchain_info({})", self.uri()))
+    pub fn get_blockchain_info(&self) -> Result<ChainInfo, RpcError> {
+        self.read("get_blockchain_info", json!([]))
     }
 
-    pub fn send_transaction(&self, tx: Transaction) -> H256 {
-        self.inner
-            .lock()
-            .send_transaction(tx.clone())
-            .call()
-            .unwrap_or_else(|_| panic!("Jsonrpc::send_transaction({}, {:?})", self.uri(), tx))
+    pub fn send_transaction(&self, tx: Transaction) -> Result<H256, RpcError> {
+        self.call("send_transaction", json!([tx]))
     }
 
-    pub fn broadcast_transaction(&self, tx: Transaction) -> H256 {
-        self.inner
-            .lock()
-            .broadcast_transaction(tx.clone())
-            .call()
-            .unwrap_or_else(|_| panic!("Jsonrpc::broadcast_transaction({}, {:?})", self.uri(), tx))
+    pub fn broadcast_transaction(&self, tx: Transaction) -> Result<H256, RpcError> {
+        self.call("broadcast_transaction", json!([tx]))
     }
 
-    pub fn send_transaction_result(&self, tx: Transaction) -> JsonRpcResult<H256> {
-        self.inner.lock().send_transaction(tx).call()
+    /// `send_transaction` with the error classified into `SendError`.
+    pub fn send_transaction_result(&self, tx: Transaction) -> Result<H256, SendError> {
+        self.send_transaction(tx).map_err(SendError::from)
     }
 
-    pub fn tx_pool_info(&self) -> TxPoolInfo {
-        self.inner
-            .lock()
-            .tx_pool_info()
-            .call()
-            .unwrap_or_else(|_| panic!("Jsonrpc::tx_pool_info({})", self.uri()))
+    pub fn tx_pool_info(&self) -> Result<TxPoolInfo, RpcError> {
+        self.read("tx_pool_info", json!([]))
     }
 
-    pub fn add_node(&self, peer_id: String, address: String) {
-        self.inner
-            .lock()
-            .add_node(peer_id.clone(), address.clone())
-            .call()
-            .unwrap_or_else(|_| {
-                panic!(
-                    "Jsonrpc::add_node({}, {}, {})",
-                    self.uri(),
-                    peer_id,
-                    address
-                )
-            });
-    }
-
-    pub fn remove_node(&self, peer_id: String) {
-        self.inner
-            .lock()
-            .remove_node(peer_id.clone())
-            .call()
-            .unwrap_or_else(|_| panic!("Jsonrpc::remove_node({}, {})", self.uri(), peer_id))
+    pub fn add_node(&self, peer_id: String, address: String) -> Result<(), RpcError> {
+        self.call("add_node", json!([peer_id, address]))
     }
 
-    pub fn process_block_without_verify(&self, block: Block) -> Option<H256> {
-        self.inner
-            .lock()
-            .process_block_without_verify(block.clone())
-            .call()
-            .unwrap_or_else(|_| {
-                panic!(
-                    "Jsonrpc::process_block_without_verify({}, {:?})",
-                    self.uri(),
-                    block
-                )
-            })
+    pub fn remove_node(&self, peer_id: String) -> Result<(), RpcError> {
+        self.call("remove_node", json!([peer_id]))
+    }
+
+    pub fn process_block_without_verify(&self, block: Block) -> Result<Option<H256>, RpcError> {
+        self.call("process_block_without_verify", json!([block]))
     }
 }
 
-jsonrpc_client!(pub struct Inner {
-    pub fn get_block(&mut self, _hash: H256) -> RpcRequest<Option<BlockView>>;
-    pub fn get_block_by_number(&mut self, _number: BlockNumber) -> RpcRequest<Option<BlockView>>;
-    pub fn get_header_by_number(&mut self, _number: BlockNumber) -> RpcRequest<Option<HeaderView>>;
-    pub fn get_transaction(&mut self, _hash: H256) -> RpcRequest<Option<TransactionWithStatus>>;
-    pub fn get_block_hash(&mut self, _number: BlockNumber) -> RpcRequest<Option<H256>>;
-    pub fn get_tip_header(&mut self) -> RpcRequest<HeaderView>;
-    pub fn get_cells_by_lock_hash(
-        &mut self,
-        _lock_hash: H256,
-        _from: BlockNumber,
-        _to: BlockNumber
-    ) -> RpcRequest<Vec<CellOutputWithOutPoint>>;
-    pub fn get_live_cell(&mut self, _out_point: OutPoint) -> RpcRequest<CellWithStatus>;
-    pub fn get_tip_block_number(&mut self) -> RpcRequest<BlockNumber>;
-    pub fn local_node_info(&mut self) -> RpcRequest<LocalNode>;
-    pub fn get_peers(&mut self) -> RpcRequest<Vec<RemoteNode>>;
-    pub fn get_block_template(
-        &mut self,
-        bytes_limit: Option<Uint64>,
-        proposals_limit: Option<Uint64>,
-        max_version: Option<Version>
-    ) -> RpcRequest<BlockTemplate>;
-    pub fn submit_block(&mut self, _work_id: String, _data: Block) -> RpcRequest<Option<H256>>;
-    pub fn get_blockchain_info(&mut self) -> RpcRequest<ChainInfo>;
-    pub fn get_peers_state(&mut self) -> RpcRequest<Vec<PeerState>>;
-    pub fn compute_transaction_hash(&mut self, tx: Transaction) -> RpcRequest<H256>;
-    pub fn dry_run_transaction(&mut self, _tx: Transaction) -> RpcRequest<DryRunResult>;
-    pub fn send_transaction(&mut self, tx: Transaction) -> RpcRequest<H256>;
-    pub fn broadcast_transaction(&mut self, tx: Transaction) -> RpcRequest<H256>;
-    pub fn tx_pool_info(&mut self) -> RpcRequest<TxPoolInfo>;
-
-    pub fn add_node(&mut self, peer_id: String, address: String) -> RpcRequest<()>;
-    pub fn remove_node(&mut self, peer_id: String) -> RpcRequest<()>;
-    pub fn process_block_without_verify(&mut self, _data: Block) -> RpcRequest<Option<H256>>;
-});
+async fn execute(request: reqwest::RequestBuilder) -> Result<Value, RpcError> {
+    let response = request
+        .send()
+        .await
+        .map_err(|err| RpcError::Transport(err.to_string()))?;
+    let status = response.status();
+    if !status.is_success() {
+        let message = response.text().await.unwrap_or_default();
+        return Err(RpcError::Status {
+            status: status.as_u16(),
+            message,
+        });
+    }
+    response
+        .json::<Value>()
+        .await
+        .map_err(|err| RpcError::Transport(format!("status {}, {}", status, err)))
+}
+
+fn decode_response<T: DeserializeOwned>(mut response: Value) -> Result<T, RpcError> {
+    if let Some(error) = response.get("error").filter(|error| !error.is_null()) {
+        return Err(RpcError::Response {
+            code: error.get("code").and_then(Value::as_i64).unwrap_or(0),
+            message: error
+                .get("message")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_owned(),
+        });
+    }
+    match response.get_mut("result") {
+        Some(result) => {
+            serde_json::from_value(result.take()).map_err(|err| RpcError::Decode(err.to_string()))
+        }
+        None => Err(RpcError::Decode(format!("no result: {}", response))),
+    }
+}
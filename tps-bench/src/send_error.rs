@@ -0,0 +1,167 @@
+use crate::net::Net;
+use crate::rpc::RpcError;
+
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// The kinds of the `send_transaction` errors.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum SendErrorKind {
+    /// The tx pool is full, the transaction may be resent later
+    PoolFull,
+    /// The transaction is already in the tx pool
+    Duplicated,
+    /// The inputs are dead, i.e. spent by another transaction, mostly after chain reorgs
+    DoubleSpend,
+    LowFeeRate,
+    /// Failed to resolve or verify the transaction, other than double spending
+    VerificationFailed,
+    /// The request did not get a JSON-RPC response
+    Transport,
+    Other,
+}
+
+#[derive(Debug, Clone)]
+pub struct SendError {
+    pub kind: SendErrorKind,
+    pub message: String,
+}
+
+// The error codes of ckb RPC, newer ckb versions only. Older ones put the reason into the message
+const POOL_IS_FULL: i64 = -1106;
+const POOL_REJECTED_DUPLICATED_TRANSACTION: i64 = -1107;
+const POOL_REJECTED_TRANSACTION_BY_MIN_FEE_RATE: i64 = -1104;
+const TRANSACTION_FAILED_TO_RESOLVE: i64 = -301;
+const TRANSACTION_FAILED_TO_VERIFY: i64 = -302;
+
+impl SendErrorKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            SendErrorKind::PoolFull => "pool_full",
+            SendErrorKind::Duplicated => "duplicated",
+            SendErrorKind::DoubleSpend => "double_spend",
+            SendErrorKind::LowFeeRate => "low_fee_rate",
+            SendErrorKind::VerificationFailed => "verification_failed",
+            SendErrorKind::Transport => "transport",
+            SendErrorKind::Other => "other",
+        }
+    }
+
+    fn classify(code: i64, message: &str) -> Self {
+        if code == POOL_IS_FULL
+            || message.contains("PoolIsFull")
+            || message.contains("TransactionPoolFull")
+        {
+            SendErrorKind::PoolFull
+        } else if code == POOL_REJECTED_DUPLICATED_TRANSACTION || message.contains("Duplicated") {
+            SendErrorKind::Duplicated
+        } else if message.contains("Dead") {
+            SendErrorKind::DoubleSpend
+        } else if code == POOL_REJECTED_TRANSACTION_BY_MIN_FEE_RATE
+            || message.contains("LowFeeRate")
+        {
+            SendErrorKind::LowFeeRate
+        } else if code == TRANSACTION_FAILED_TO_RESOLVE
+            || code == TRANSACTION_FAILED_TO_VERIFY
+            || message.contains("Unknown")
+            || message.contains("Verification")
+            || message.contains("Script")
+        {
+            SendErrorKind::VerificationFailed
+        } else {
+            SendErrorKind::Other
+        }
+    }
+}
+
+impl From<RpcError> for SendError {
+    fn from(err: RpcError) -> Self {
+        let kind = match &err {
+            RpcError::Response { code, message } => SendErrorKind::classify(*code, message),
+            RpcError::Transport(_) | RpcError::Status { .. } => SendErrorKind::Transport,
+            RpcError::Decode(message) | RpcError::NotFound(message) => {
+                SendErrorKind::classify(0, message)
+            }
+        };
+        Self {
+            kind,
+            message: err.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for SendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.kind.label(), self.message)
+    }
+}
+
+/// Counts the accepted transactions and the `send_transaction` errors by kind, per endpoint.
+#[derive(Clone, Default)]
+pub struct SendErrorCounter {
+    inner: Arc<Mutex<Vec<EndpointSendCounts>>>,
+}
+
+#[derive(Clone, Default)]
+struct EndpointSendCounts {
+    accepted: u64,
+    errors: BTreeMap<SendErrorKind, u64>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct EndpointSendErrors {
+    endpoint: String,
+    /// The `send_transaction` requests, including the retries after `pool_full`
+    requests: u64,
+    accepted: u64,
+    rejected: u64,
+    /// `rejected / requests`
+    rejection_rate: f64,
+    errors: BTreeMap<SendErrorKind, u64>,
+}
+
+impl SendErrorCounter {
+    pub fn new(endpoints: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(vec![EndpointSendCounts::default(); endpoints])),
+        }
+    }
+
+    pub fn record_accepted(&self, endpoint: usize) {
+        self.inner.lock().unwrap()[endpoint].accepted += 1;
+    }
+
+    pub fn record_error(&self, endpoint: usize, kind: SendErrorKind) {
+        *self.inner.lock().unwrap()[endpoint]
+            .errors
+            .entry(kind)
+            .or_default() += 1;
+    }
+
+    pub fn metrics(&self, net: &Net) -> Vec<EndpointSendErrors> {
+        let inner = self.inner.lock().unwrap();
+        net.endpoints()
+            .iter()
+            .zip(inner.iter())
+            .map(|(rpc, counts)| {
+                let rejected = counts.errors.values().sum::<u64>();
+                let requests = counts.accepted + rejected;
+                EndpointSendErrors {
+                    endpoint: rpc.uri().clone(),
+                    requests,
+                    accepted: counts.accepted,
+                    rejected,
+                    rejection_rate: if requests == 0 {
+                        0.0
+                    } else {
+                        rejected as f64 / requests as f64
+                    },
+                    errors: counts.errors.clone(),
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,227 @@
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default)]
+pub struct Percentiles {
+    pub count: u64,
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub max: u64,
+}
+
+impl Percentiles {
+    pub fn new(samples: &[u64]) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+
+        let mut sorted = samples.to_vec();
+        sorted.sort_unstable();
+        Self {
+            count: sorted.len() as u64,
+            p50: nearest_rank(&sorted, 50),
+            p90: nearest_rank(&sorted, 90),
+            p99: nearest_rank(&sorted, 99),
+            max: *sorted.last().unwrap(),
+        }
+    }
+}
+
+// Nearest-rank percentile of the sorted samples
+fn nearest_rank(sorted: &[u64], percentile: usize) -> u64 {
+    let rank = (percentile * sorted.len() + 99) / 100;
+    sorted[rank.max(1) - 1]
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default)]
+pub struct Summary {
+    pub count: u64,
+    pub mean: f64,
+    /// Sample standard deviation
+    pub standard_deviation: f64,
+    /// The 95% confidence interval of the mean, by Student's t-distribution
+    pub confidence_interval: (f64, f64),
+    /// `standard_deviation / mean`
+    pub coefficient_of_variation: f64,
+}
+
+impl Summary {
+    pub fn new(samples: &[f64]) -> Self {
+        let count = samples.len();
+        if count == 0 {
+            return Self::default();
+        }
+
+        let mean = samples.iter().sum::<f64>() / count as f64;
+        if count == 1 {
+            return Self {
+                count: 1,
+                mean,
+                confidence_interval: (mean, mean),
+                ..Default::default()
+            };
+        }
+
+        let variance = samples
+            .iter()
+            .map(|sample| (sample - mean) * (sample - mean))
+            .sum::<f64>()
+            / (count - 1) as f64;
+        let standard_deviation = variance.sqrt();
+        let margin = t_critical_value(count - 1) * standard_deviation / (count as f64).sqrt();
+        let coefficient_of_variation = if mean.abs() <= std::f64::EPSILON {
+            0.0
+        } else {
+            standard_deviation / mean
+        };
+        Self {
+            count: count as u64,
+            mean,
+            standard_deviation,
+            confidence_interval: (mean - margin, mean + margin),
+            coefficient_of_variation,
+        }
+    }
+}
+
+// Two-tailed 95% critical values of Student's t-distribution, indexed by `degrees_of_freedom - 1`
+const T_CRITICAL_VALUES: [f64; 30] = [
+    12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160,
+    2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056,
+    2.052, 2.048, 2.045, 2.042,
+];
+
+fn t_critical_value(degrees_of_freedom: usize) -> f64 {
+    T_CRITICAL_VALUES
+        .get(degrees_of_freedom - 1)
+        .copied()
+        .unwrap_or(1.96)
+}
+
+/// Whether the means of `a` and `b` differ significantly at 95% confidence, by Welch's t-test.
+/// Return `None` if either has less than 2 samples.
+pub fn is_significant(a: &Summary, b: &Summary) -> Option<bool> {
+    if a.count < 2 || b.count < 2 {
+        return None;
+    }
+
+    let variance_a = a.standard_deviation * a.standard_deviation / a.count as f64;
+    let variance_b = b.standard_deviation * b.standard_deviation / b.count as f64;
+    let variance = variance_a + variance_b;
+    if variance <= std::f64::EPSILON {
+        return Some((a.mean - b.mean).abs() > std::f64::EPSILON);
+    }
+    let t = (a.mean - b.mean).abs() / variance.sqrt();
+    // Welch–Satterthwaite equation
+    let degrees_of_freedom = variance * variance
+        / (variance_a * variance_a / (a.count - 1) as f64
+            + variance_b * variance_b / (b.count - 1) as f64);
+    Some(t > t_critical_value((degrees_of_freedom.floor() as usize).max(1)))
+}
+
+/// Pearson correlation coefficient of `xs` and `ys`. Return 0.0 when it is undefined.
+pub fn correlation(xs: &[f64], ys: &[f64]) -> f64 {
+    let n = xs.len().min(ys.len());
+    if n < 2 {
+        return 0.0;
+    }
+
+    let (xs, ys) = (&xs[..n], &ys[..n]);
+    let mean_x = xs.iter().sum::<f64>() / n as f64;
+    let mean_y = ys.iter().sum::<f64>() / n as f64;
+    let (mut covariance, mut variance_x, mut variance_y) = (0.0, 0.0, 0.0);
+    for (x, y) in xs.iter().zip(ys.iter()) {
+        covariance += (x - mean_x) * (y - mean_y);
+        variance_x += (x - mean_x) * (x - mean_x);
+        variance_y += (y - mean_y) * (y - mean_y);
+    }
+    if variance_x <= std::f64::EPSILON || variance_y <= std::f64::EPSILON {
+        return 0.0;
+    }
+    covariance / (variance_x * variance_y).sqrt()
+}
+
+/// Autocorrelation of `xs` at `lag`, normalized by the variance. Return 0.0 when it is undefined.
+pub fn autocorrelation(xs: &[f64], lag: usize) -> f64 {
+    if lag >= xs.len() {
+        return 0.0;
+    }
+
+    let mean = xs.iter().sum::<f64>() / xs.len() as f64;
+    let variance = xs.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>();
+    if variance <= std::f64::EPSILON {
+        return 0.0;
+    }
+    let covariance = xs
+        .iter()
+        .zip(xs[lag..].iter())
+        .map(|(x, y)| (x - mean) * (y - mean))
+        .sum::<f64>();
+    covariance / variance
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_nearest_rank_percentiles() {
+        let percentiles = Percentiles::new(&(1..=100).rev().collect::<Vec<_>>());
+        assert_eq!(percentiles.count, 100);
+        assert_eq!(percentiles.p50, 50);
+        assert_eq!(percentiles.p90, 90);
+        assert_eq!(percentiles.p99, 99);
+        assert_eq!(percentiles.max, 100);
+
+        // The rank is rounded up, so a few samples never interpolate
+        let percentiles = Percentiles::new(&[3, 1, 2]);
+        assert_eq!(percentiles.p50, 2);
+        assert_eq!(percentiles.p90, 3);
+        assert_eq!(percentiles.p99, 3);
+
+        assert_eq!(Percentiles::new(&[]).count, 0);
+    }
+
+    #[test]
+    fn test_confidence_interval_by_t_table() {
+        let summary = Summary::new(&[1.0, 2.0, 3.0, 4.0, 5.0]);
+        let standard_deviation = 2.5f64.sqrt();
+        let margin = 2.776 * standard_deviation / 5f64.sqrt();
+        assert_eq!(summary.count, 5);
+        assert_close(summary.mean, 3.0);
+        assert_close(summary.standard_deviation, standard_deviation);
+        assert_close(summary.confidence_interval.0, 3.0 - margin);
+        assert_close(summary.confidence_interval.1, 3.0 + margin);
+        assert_close(summary.coefficient_of_variation, standard_deviation / 3.0);
+    }
+
+    #[test]
+    fn test_confidence_interval_beyond_t_table() {
+        // 39 degrees of freedom is out of the table, so the normal 1.96 is taken
+        let samples = (0..40)
+            .map(|index| (index % 2 * 2) as f64)
+            .collect::<Vec<_>>();
+        let summary = Summary::new(&samples);
+        let margin = 1.96 * (40.0f64 / 39.0).sqrt() / 40f64.sqrt();
+        assert_close(summary.mean, 1.0);
+        assert_close(summary.confidence_interval.0, 1.0 - margin);
+        assert_close(summary.confidence_interval.1, 1.0 + margin);
+    }
+
+    #[test]
+    fn test_confidence_interval_of_single_sample() {
+        let summary = Summary::new(&[7.0]);
+        assert_eq!(summary.count, 1);
+        assert_close(summary.confidence_interval.0, 7.0);
+        assert_close(summary.confidence_interval.1, 7.0);
+    }
+}
@@ -0,0 +1,235 @@
+use crate::account::Account;
+use crate::global::MIN_SECP_CELL_CAPACITY;
+use crate::latency::TRANSACTION_COMMITMENT_TIMEOUT;
+use crate::miner::Miner;
+use crate::net::Net;
+use crate::rpc::RpcError;
+use crate::transfer::{construct_unsigned_transaction, sign_transaction};
+use crate::util::{call_until_ok, estimate_fee, estimate_fee_by_size};
+use crate::utxo::UTXO;
+
+use ckb_jsonrpc_types::Status;
+use ckb_types::core::TransactionView;
+use ckb_types::packed::{Byte32, OutPoint};
+use ckb_types::prelude::*;
+use log::{error, info};
+use std::time::Instant;
+
+pub const DEFAULT_MAX_TRANSACTION_BYTES: u64 = 500_000;
+
+// Conservative serialized sizes used to decide how many inputs fit into a transaction
+const TRANSACTION_BASE_BYTES: u64 = 512; // raw transaction fields, cell deps and witness
+const INPUT_BYTES: u64 = 44;
+const OUTPUT_BYTES: u64 = 128; // cell output and its data
+
+/// Merge the matured cells owned by `account` into `cells` cells.
+///
+/// Each round merges the cells via many-input transactions which respect
+/// `max_transaction_bytes`, then drives `miner` until all of them are committed. The last round
+/// splits the merged capacity into exactly `cells` outputs.
+pub fn sweep(
+    net: &Net,
+    miner: &Miner,
+    account: &Account,
+    cells: usize,
+    max_transaction_bytes: u64,
+) -> Result<(), RpcError> {
+    info!(
+        "[START] sweep::sweep(cells: {}, max_transaction_bytes: {})",
+        cells, max_transaction_bytes
+    );
+    let tip_header = call_until_ok("net.get_confirmed_tip_header", || {
+        net.get_confirmed_tip_header()
+    })?;
+    let (utxoset, unmatureds) = account.pull_until(net, &tip_header)?;
+    let (mut utxos, _) = account.construct_utxo_vec(utxoset, unmatureds);
+    if utxos.len() <= cells {
+        info!("[END] sweep::sweep, only {} matured cells", utxos.len());
+        return Ok(());
+    }
+
+    let total_capacity = utxos.iter().map(|utxo| utxo.capacity()).sum::<u64>();
+    if total_capacity < cells as u64 * MIN_SECP_CELL_CAPACITY {
+        prompt_and_exit!(
+            "sweep::sweep total capacity {} is not enough for {} cells",
+            total_capacity,
+            cells
+        );
+    }
+
+    for round in 1.. {
+        let mut groups = div_ceil(utxos.len(), max_inputs_count(max_transaction_bytes, 1));
+        let final_round = groups <= cells;
+        if final_round {
+            // More outputs per transaction leave less room for inputs
+            loop {
+                let outputs_count = div_ceil(cells, groups);
+                let max_inputs = max_inputs_count(max_transaction_bytes, outputs_count);
+                let new_groups = div_ceil(utxos.len(), max_inputs);
+                if new_groups <= groups {
+                    break;
+                }
+                groups = new_groups;
+            }
+        }
+
+        info!(
+            "sweep round {}: merge {} cells via {} transactions",
+            round,
+            utxos.len(),
+            groups
+        );
+        if final_round {
+            // Deal the largest cells first so that the groups get similar capacities, and the
+            // groups with one more output get the larger ones
+            utxos.sort_by(|a, b| b.capacity().cmp(&a.capacity()));
+        }
+        // Dealing round-robin keeps every group within `utxos.len() / groups` + 1 inputs
+        let mut inputs_groups = (0..groups).map(|_| Vec::new()).collect::<Vec<_>>();
+        for (index, utxo) in utxos.drain(..).enumerate() {
+            inputs_groups[index % groups].push(utxo);
+        }
+        let mut transactions = Vec::with_capacity(groups);
+        for (index, inputs) in inputs_groups.into_iter().enumerate() {
+            let outputs_count = if final_round {
+                cells / groups + if index < cells % groups { 1 } else { 0 }
+            } else {
+                1
+            };
+            let transaction = build_transaction(account, inputs, outputs_count as u64);
+            if transaction.data().serialized_size_in_block() as u64 > max_transaction_bytes {
+                prompt_and_exit!(
+                    "sweep::sweep transaction {:#x} exceeds {} bytes",
+                    transaction.hash(),
+                    max_transaction_bytes
+                );
+            }
+            if let Err(err) = net.send_transaction_result(transaction.data().into()) {
+                prompt_and_exit!(
+                    "sweep::sweep send_transaction({:#x}) error: {}",
+                    transaction.hash(),
+                    err
+                );
+            }
+            transactions.push(transaction);
+        }
+
+        let hashes = transactions.iter().map(|tx| tx.hash()).collect();
+        wait_committed(net, miner, hashes);
+
+        utxos = transactions.iter().flat_map(outputs_as_utxos).collect();
+        if final_round {
+            break;
+        }
+    }
+    info!("[END] sweep::sweep, {} cells", utxos.len());
+    Ok(())
+}
+
+fn build_transaction(account: &Account, inputs: Vec<UTXO>, outputs_count: u64) -> TransactionView {
+    let inputs_capacity = inputs.iter().map(|input| input.capacity()).sum::<u64>();
+    let required_capacity = outputs_count * MIN_SECP_CELL_CAPACITY + estimate_fee(outputs_count);
+    if inputs_capacity < required_capacity {
+        prompt_and_exit!(
+            "sweep::sweep {} inputs with capacity {} are not enough for {} outputs, require {}",
+            inputs.len(),
+            inputs_capacity,
+            outputs_count,
+            required_capacity
+        );
+    }
+    let raw_transaction = construct_unsigned_transaction(account, inputs, outputs_count);
+
+    // `construct_unsigned_transaction` pays the fee according to the outputs count, the extra
+    // fee for the inputs is taken from the first output
+    let transaction_size =
+        raw_transaction.data().serialized_size_in_block() as u64 + TRANSACTION_BASE_BYTES;
+    let extra_fee =
+        estimate_fee_by_size(transaction_size).saturating_sub(estimate_fee(outputs_count));
+    let mut outputs = raw_transaction.outputs().into_iter().collect::<Vec<_>>();
+    let capacity: u64 = outputs[0].capacity().unpack();
+    let capacity = match capacity.checked_sub(extra_fee) {
+        Some(capacity) if capacity >= MIN_SECP_CELL_CAPACITY => capacity,
+        _ => prompt_and_exit!(
+            "sweep::sweep output capacity {} cannot pay the extra fee {} and keep {}",
+            capacity,
+            extra_fee,
+            MIN_SECP_CELL_CAPACITY
+        ),
+    };
+    outputs[0] = outputs[0]
+        .clone()
+        .as_builder()
+        .capacity(capacity.pack())
+        .build();
+    let raw_transaction = raw_transaction
+        .as_advanced_builder()
+        .set_outputs(outputs)
+        .build();
+
+    sign_transaction(account, raw_transaction)
+}
+
+// Mine until `hashes` are all committed. A transaction rejected or evicted from the pool is never
+// committed, so give up after `TRANSACTION_COMMITMENT_TIMEOUT`.
+fn wait_committed(net: &Net, miner: &Miner, mut hashes: Vec<Byte32>) {
+    let deadline = Instant::now() + TRANSACTION_COMMITMENT_TIMEOUT;
+    while !hashes.is_empty() {
+        if Instant::now() > deadline {
+            prompt_and_exit!(
+                "sweep::wait_committed {} transactions not committed within {:?}, e.g. {:#x}",
+                hashes.len(),
+                TRANSACTION_COMMITMENT_TIMEOUT,
+                hashes[0]
+            );
+        }
+        if miner.generate_block().is_none() {
+            error!("sweep::wait_committed failed to generate block");
+        }
+        hashes.retain(|hash| {
+            net.get_transaction(hash.clone())
+                .ok()
+                .flatten()
+                .map(|tx| tx.tx_status.status != Status::Committed)
+                .unwrap_or(true)
+        });
+        info!(
+            "sweep waiting {} transactions to be committed",
+            hashes.len()
+        );
+    }
+}
+
+fn outputs_as_utxos(transaction: &TransactionView) -> Vec<UTXO> {
+    transaction
+        .outputs()
+        .into_iter()
+        .enumerate()
+        .map(|(index, output)| {
+            let out_point = OutPoint::new_builder()
+                .tx_hash(transaction.hash())
+                .index(index.pack())
+                .build();
+            UTXO::new(output, out_point)
+        })
+        .collect()
+}
+
+fn max_inputs_count(max_transaction_bytes: u64, outputs_count: usize) -> usize {
+    let available = max_transaction_bytes
+        .saturating_sub(TRANSACTION_BASE_BYTES)
+        .saturating_sub(outputs_count as u64 * OUTPUT_BYTES);
+    // A transaction merges at least 2 inputs, otherwise sweeping never ends
+    if available < 2 * INPUT_BYTES {
+        prompt_and_exit!(
+            "sweep::sweep max_transaction_bytes {} cannot hold 2 inputs and {} outputs",
+            max_transaction_bytes,
+            outputs_count
+        );
+    }
+    (available / INPUT_BYTES) as usize
+}
+
+fn div_ceil(a: usize, b: usize) -> usize {
+    (a + b - 1) / b
+}
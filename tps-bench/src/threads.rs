@@ -2,15 +2,17 @@
 use ckb_types::packed::{CellOutput, OutPoint};
 use ckb_types::prelude::*;
 use crossbeam_channel::{bounded, Receiver};
-use log::info;
+use log::{error, info};
 use std::collections::HashMap;
-use std::thread::{sleep, spawn, JoinHandle};
+use std::thread::{spawn, JoinHandle};
 
 use crate::account::Account;
 use crate::config::Config;
 use crate::miner::Miner;
 use crate::net::Net;
+use crate::rpc::RpcError;
 use crate::transfer::{construct_unsigned_transaction, sign_transaction};
+use crate::util::call_until_ok;
 use crate::utxo::UTXO;
 
 // TODO move inside Account
@@ -18,16 +20,18 @@ pub fn spawn_pull_utxos(
     config: &Config,
     account: &Account,
     miner: &Miner,
-) -> (JoinHandle<()>, Receiver<UTXO>) {
+) -> Result<(JoinHandle<()>, Receiver<UTXO>), RpcError> {
     let net = Net::connect_all(config.rpc_urls());
-    let current_header = net.get_confirmed_tip_header();
-    let (mut utxoset, mut unmatureds) = account.pull_until(&net, &current_header);
+    let current_header = call_until_ok("net.get_confirmed_tip_header", || {
+        net.get_confirmed_tip_header()
+    })?;
+    let (mut utxoset, mut unmatureds) = account.pull_until(&net, &current_header)?;
 
     let mut total_capacity = get_total_capacity_from_utxo(&utxoset);
 
     while total_capacity < config.ensure_matured_capacity_greater_than {
         if let Some(block_number) = miner.generate_block() {
-            account.pull_from_block_number(&net, block_number, &mut utxoset, &mut unmatureds);
+            account.pull_from_block_number(&net, block_number, &mut utxoset, &mut unmatureds)?;
             total_capacity = get_total_capacity_from_utxo(&utxoset);
         }
     }
@@ -42,7 +46,7 @@ pub fn spawn_pull_utxos(
         account.pull_forever(net, current_header, unmatureds, utxo_sender);
     });
 
-    (handler, utxo_receiver)
+    Ok((handler, utxo_receiver))
 }
 
 pub fn spawn_transfer_utxos(
@@ -59,7 +63,12 @@ pub fn spawn_transfer_utxos(
         while let Ok(utxo) = utxo_receiver.recv() {
             let raw = construct_unsigned_transaction(&recipient, vec![utxo], 1);
             let signed = sign_transaction(&sender, raw);
-            net.send_transaction(signed.data().into());
+            if let Err(err) = net.send_transaction(signed.data().into()) {
+                error!(
+                    "threads::spawn_transfer_utxos send_transaction error: {}",
+                    err
+                );
+            }
         }
     })
 }
@@ -67,13 +76,23 @@ pub fn spawn_transfer_utxos(
 pub fn spawn_miner(miner: &Miner) {
     info!("threads::spawn_miner");
     miner.assert_block_assembler();
+    miner.spawn_pool_sampler();
     let miner = miner.clone();
+    let mut block_intervals = miner.block_intervals();
     spawn(move || loop {
-        sleep(miner.block_time);
+        miner.wait_next_block(&mut block_intervals);
         miner.generate_block();
     });
 }
 
+pub fn spawn_competing_miners(config: &Config) {
+    info!("threads::spawn_competing_miners");
+    for (index, competing_miner) in config.competing_miners.iter().enumerate() {
+        let miner = Miner::from_competing(competing_miner, index);
+        spawn_miner(&miner);
+    }
+}
+
 fn get_total_capacity_from_utxo(utxoset: &HashMap<OutPoint, CellOutput>) -> u64 {
     utxoset
         .iter()
@@ -0,0 +1,97 @@
+use crate::config::Config;
+use crate::global::{BENCH_PHASE, RUN_ID};
+use crate::latency::unix_time_ms;
+use crate::rpc::Jsonrpc;
+
+use log::{error, info};
+use serde_derive::Serialize;
+use serde_json::json;
+use std::fs::OpenOptions;
+use std::io::{BufWriter, Write};
+use std::thread::{sleep, spawn};
+use std::time::{Duration, Instant};
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Serialize, Debug)]
+struct TxPoolSample<'a> {
+    run_id: &'a str,
+    timestamp: u64,
+    phase: &'a str,
+    endpoint: &'a str,
+    pending: u64,
+    proposed: u64,
+    orphan: u64,
+    total_tx_size: u64,
+    total_tx_cycles: u64,
+}
+
+/// Mark the current phase of the run, recorded along with the tx pool samples.
+pub fn set_bench_phase(phase: String) {
+    info!("[PHASE] {}", phase);
+    *BENCH_PHASE.lock().unwrap() = phase;
+}
+
+/// Record `tx_pool_info` of every endpoint once per second for the rest of the process, as JSON
+/// lines appended into `txpool.jsonl` in `data_dir`.
+pub fn spawn_txpool_recorder(config: &Config) {
+    let path = config.txpool_series_path();
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .unwrap_or_else(|err| panic!("open {} error: {}", path.to_string_lossy(), err));
+    info!(
+        "TPSBench appends tx pool samples into {}",
+        path.to_string_lossy()
+    );
+
+    let endpoints = config
+        .rpc_urls()
+        .into_iter()
+        .map(Jsonrpc::connect)
+        .collect::<Vec<_>>();
+    spawn(move || {
+        let mut writer = BufWriter::new(file);
+        let mut next_sample = Instant::now();
+        loop {
+            let phase = BENCH_PHASE.lock().unwrap().clone();
+            for rpc in endpoints.iter() {
+                let tx_pool_info = match rpc.tx_pool_info() {
+                    Ok(tx_pool_info) => tx_pool_info,
+                    Err(err) => {
+                        error!(
+                            "spawn_txpool_recorder tx_pool_info({}) error: {}",
+                            rpc.uri(),
+                            err
+                        );
+                        continue;
+                    }
+                };
+                let sample = TxPoolSample {
+                    run_id: &RUN_ID,
+                    timestamp: unix_time_ms(),
+                    phase: &phase,
+                    endpoint: rpc.uri(),
+                    pending: tx_pool_info.pending.value(),
+                    proposed: tx_pool_info.proposed.value(),
+                    orphan: tx_pool_info.orphan.value(),
+                    total_tx_size: tx_pool_info.total_tx_size.value(),
+                    total_tx_cycles: tx_pool_info.total_tx_cycles.value(),
+                };
+                let _ = writeln!(writer, "{}", json!(sample));
+            }
+            if let Err(err) = writer.flush() {
+                error!("spawn_txpool_recorder flush error: {}", err);
+            }
+
+            next_sample += SAMPLE_INTERVAL;
+            let now = Instant::now();
+            if next_sample > now {
+                sleep(next_sample - now);
+            } else {
+                next_sample = now;
+            }
+        }
+    });
+}
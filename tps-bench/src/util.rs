@@ -9,7 +9,32 @@ macro_rules! prompt_and_exit {
     })
 }
 
+use crate::rpc::RpcError;
+
+const MIN_FEE_RATE: u64 = 1000; // shannons/KB
+
 pub(crate) fn estimate_fee(outputs_count: u64) -> u64 {
-    let min_fee_rate = 1000u64; // shannons/KB
-    outputs_count * min_fee_rate
+    outputs_count * MIN_FEE_RATE
+}
+
+pub(crate) fn estimate_fee_by_size(transaction_size: u64) -> u64 {
+    (transaction_size * MIN_FEE_RATE + 999) / 1000
+}
+
+/// Call `call` until it succeeds or fails with a permanent error, logging every transient one.
+/// The RPC layer already retries the transient errors a few times, this is for waiting out an
+/// unavailable node rather than aborting the whole run.
+pub(crate) fn call_until_ok<T>(
+    what: &str,
+    mut call: impl FnMut() -> Result<T, RpcError>,
+) -> Result<T, RpcError> {
+    loop {
+        match call() {
+            Err(err) if err.is_transient() => {
+                log::error!("{} error: {}, retry in 1s", what, err);
+                std::thread::sleep(std::time::Duration::from_secs(1));
+            }
+            result => return result,
+        }
+    }
 }